@@ -1,124 +1,434 @@
-use std::str::FromStr;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::rc::Rc;
+use std::sync::Arc;
 
-#[derive(PartialEq, Debug)]
-pub enum Expr {
+use crate::bigint::BigInt;
+use crate::cons_list::ConsList;
+use crate::lexer::{tokenize, Token, CHAR_NAMES};
+use crate::number::{to_rational, Rational};
+use crate::error::{Error, Result};
+use crate::span::{Source, Span};
+use crate::symbol::Symbol;
+use crate::Function;
+
+#[derive(Clone, Debug)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+// spans are ignored, so the same code read from different places compares equal
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+impl From<ExprKind> for Expr {
+    fn from(kind: ExprKind) -> Self {
+        Self { kind, span: Span::default() }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ExprKind {
     Sym(String),
     Atom(Val),
     Func(String, Vec<Expr>),
+    List(Vec<Expr>),
+    // `{k v ...}`, whose keys and values are evaluated
+    Map(Vec<(Expr, Expr)>),
+    // `#{x ...}`
+    Set(Vec<Expr>),
+    // `[x ...]`
+    Vector(Vec<Expr>),
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, Debug)]
 pub enum Val {
+    Nil,
     Int(i64),
+    // an integer that doesn't fit in an `Int`
+    Big(BigInt),
+    // a fraction that isn't whole
+    Ratio(Rational),
     Float(f64),
+    Bool(bool),
+    // a Unicode scalar value
+    Char(char),
     Str(String),
-    List(Vec<Val>),
+    Sym(Symbol),
+    // `:name`, which evaluates to itself
+    Keyword(Symbol),
+    List(ConsList),
+    Vector(Vec<Val>),
+    Map(BTreeMap<Val, Val>),
+    Set(BTreeSet<Val>),
+    Func(Rc<Function>),
+}
+impl Val {
+    // `#f` and `nil` are the only falsy values, everything else (including `0`, `""` and `'()`) is
+    // truthy. Every conditional form (`if`, `cond`, `when`, `unless`, `and` and `or`) decides with this.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Val::Bool(false) | Val::Nil)
+    }
+
+    // values of different types are ordered by type, in this order
+    fn rank(&self) -> u8 {
+        match self {
+            Val::Nil => 0,
+            Val::Bool(_) => 1,
+            Val::Int(_) | Val::Big(_) | Val::Ratio(_) => 2,
+            Val::Float(_) => 3,
+            Val::Char(_) => 4,
+            Val::Str(_) => 5,
+            Val::Sym(_) => 6,
+            Val::Keyword(_) => 7,
+            Val::List(_) => 8,
+            Val::Vector(_) => 9,
+            Val::Map(_) => 10,
+            Val::Set(_) => 11,
+            Val::Func(_) => 12,
+        }
+    }
+}
+// a total order, so that any value can be a map key or a set member. Exact numbers are ordered by
+// value, `1` and `1.0` are different values, floats are ordered with `total_cmp` (so `nan` equals
+// itself), and functions by identity.
+impl Ord for Val {
+    fn cmp(&self, other: &Self) -> Ordering {
+        use Val::*;
+        match (self, other) {
+            (Bool(a), Bool(b)) => a.cmp(b),
+            (Int(a), Int(b)) => a.cmp(b),
+            (Int(_) | Big(_) | Ratio(_), Int(_) | Big(_) | Ratio(_)) => to_rational(self).cmp(&to_rational(other)),
+            (Float(a), Float(b)) => a.total_cmp(b),
+            (Char(a), Char(b)) => a.cmp(b),
+            (Str(a), Str(b)) => a.cmp(b),
+            (Sym(a), Sym(b)) | (Keyword(a), Keyword(b)) => a.cmp(b),
+            (List(a), List(b)) => a.cmp(b),
+            (Vector(a), Vector(b)) => a.cmp(b),
+            (Map(a), Map(b)) => a.cmp(b),
+            (Set(a), Set(b)) => a.cmp(b),
+            (Func(a), Func(b)) => Rc::as_ptr(a).cmp(&Rc::as_ptr(b)),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+impl PartialOrd for Val {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq for Val {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+impl Eq for Val {}
+
+// Lisp syntax that reads back as the same value, so long as it doesn't contain functions (which
+// print as `#<builtin +>` or `#<lambda name>`) or symbols that couldn't have been read in the
+// first place. Lists are data, so reading `(f 1)` back and quoting it gives the list again.
+impl fmt::Display for Val {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Val::Nil => write!(f, "nil"),
+            Val::Int(i) => write!(f, "{}", i),
+            Val::Big(big) => write!(f, "{}", big),
+            Val::Ratio(ratio) => write!(f, "{}", ratio),
+            Val::Float(x) if x.is_nan() => write!(f, "nan"),
+            Val::Float(x) if x.is_infinite() => write!(f, "{}inf", if *x < 0.0 { "-" } else { "" }),
+            // unlike `Display`, this always has a point or an exponent, so it reads back as a float
+            Val::Float(x) => write!(f, "{:?}", x),
+            Val::Bool(b) => write!(f, "{}", if *b { "#t" } else { "#f" }),
+            Val::Char(c) => match CHAR_NAMES.iter().find(|(_, named)| named == c) {
+                Some((name, _)) => write!(f, "#\\{}", name),
+                None if c.is_whitespace() || c.is_control() => write!(f, "#\\x{:x}", *c as u32),
+                None => write!(f, "#\\{}", c),
+            },
+            Val::Str(s) => write_str(f, s),
+            Val::Sym(name) => write!(f, "{}", name.name()),
+            Val::Keyword(name) => write!(f, ":{}", name.name()),
+            Val::List(items) => write_seq(f, "(", items.iter(), ")"),
+            Val::Vector(items) => write_seq(f, "[", items.iter(), "]"),
+            Val::Map(map) => write_seq(f, "{", map.iter().flat_map(|(k, v)| [k, v]), "}"),
+            Val::Set(set) => write_seq(f, "#{", set.iter(), "}"),
+            Val::Func(func) => write!(f, "{}", func),
+        }
+    }
 }
 
-mod regexes {
+// the code as it would be written, which reads back as the same `Expr`
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ExprKind::Sym(name) => write!(f, "{}", name),
+            // the reader only makes these out of quoted source
+            ExprKind::Atom(val @ (Val::Sym(_) | Val::List(_) | Val::Vector(_) | Val::Map(_) | Val::Set(_))) => {
+                write!(f, "'{}", val)
+            }
+            ExprKind::Atom(val) => write!(f, "{}", val),
+            ExprKind::Func(name, args) => {
+                write!(f, "({}", name)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::List(items) => write_seq(f, "(", items.iter(), ")"),
+            ExprKind::Map(entries) => write_seq(f, "{", entries.iter().flat_map(|(k, v)| [k, v]), "}"),
+            ExprKind::Set(items) => write_seq(f, "#{", items.iter(), "}"),
+            ExprKind::Vector(items) => write_seq(f, "[", items.iter(), "]"),
+        }
+    }
+}
+
+fn write_seq<T: fmt::Display>(
+    f: &mut fmt::Formatter,
+    open: &str,
+    items: impl Iterator<Item = T>,
+    close: &str,
+) -> fmt::Result {
+    write!(f, "{}", open)?;
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    write!(f, "{}", close)
+}
+
+// with the escapes the lexer understands
+fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            '\r' => write!(f, "\\r")?,
+            '\0' => write!(f, "\\0")?,
+            c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+pub(crate) mod regexes {
     pub use regex::Regex;
 
     lazy_static! {
-        pub static ref INT: Regex = Regex::new(r"^([1-9]+||0)[0-9]*$").unwrap();
+        pub static ref KEYWORD: Regex = Regex::new(r"^:[0-9a-zA-Z_+\-*/<>=!?%&^~.]+$").unwrap();
     }
     lazy_static! {
-        pub static ref FLOAT: Regex = Regex::new(r"^([1-9]+||0)[0-9]*\.[0-9]+$").unwrap();
+        // a sign followed by a digit or a point starts a number, not a symbol, and `...` is the only
+        // symbol starting with a point
+        pub static ref SYM: Regex = Regex::new(
+            r"^(\.\.\.|([a-zA-Z_*/<>=!?%&^~]|[+\-]([a-zA-Z_+\-*/<>=!?%&^~]|$))[0-9a-zA-Z_+\-*/<>=!?%&^~.]*)$"
+        ).unwrap();
     }
-    lazy_static! {
-        pub static ref STR: Regex = Regex::new(r#"^".*"$"#).unwrap();
+}
+
+// reads `src` as a single literal, such as a number, a string or a quoted list
+pub fn try_parse_atom(src: &str) -> Result<Val> {
+    match try_parse_expr(src)?.kind {
+        ExprKind::Atom(val) => Ok(val),
+        _ => Err(Error::parse(format!("malformed value: `{}`", src), &Span::default())),
     }
-    lazy_static! {
-        pub static ref LIST: Regex = Regex::new(r"^'\(.+\)$").unwrap();
+}
+
+// turns code into the data it was written as
+pub fn quote(expr: &Expr) -> Val {
+    match &expr.kind {
+        ExprKind::Sym(name) => Val::Sym(Symbol::new(name)),
+        // collections and symbols were quoted in the source, and need to stay quoted to mean the same thing
+        ExprKind::Atom(val @ (Val::List(_) | Val::Vector(_) | Val::Map(_) | Val::Set(_) | Val::Sym(_))) => {
+            Val::List(ConsList::from(vec![Val::Sym(Symbol::new("quote")), val.clone()]))
+        }
+        ExprKind::Atom(val) => val.clone(),
+        ExprKind::Func(name, args) => {
+            Val::List(std::iter::once(Val::Sym(Symbol::new(name))).chain(args.iter().map(quote)).collect())
+        }
+        ExprKind::List(items) => Val::List(items.iter().map(quote).collect()),
+        ExprKind::Map(entries) => Val::Map(entries.iter().map(|(k, v)| (quote(k), quote(v))).collect()),
+        ExprKind::Set(items) => Val::Set(items.iter().map(quote).collect()),
+        ExprKind::Vector(items) => Val::Vector(items.iter().map(quote).collect()),
     }
+}
 
-    lazy_static! {
-        pub static ref FUNC: Regex = Regex::new(r"^\(\s*(?P<funcname>[a-zA-Z_]+[0-9a-zA-Z_]*)\s*(?P<args>.*)\)$").unwrap();
+// turns data back into code, the inverse of `quote`; code that didn't come from the source gets `span`
+pub fn to_expr(val: &Val, span: &Span) -> Expr {
+    let kind = match val {
+        Val::Sym(name) => ExprKind::Sym(name.name().to_string()),
+        // `(quote x)` is read the same way as `'x`
+        Val::List(items) => match items.to_vec().as_slice() {
+            [Val::Sym(name), quoted] if name == "quote" => ExprKind::Atom(quoted.clone()),
+            [Val::Sym(name), args @ ..] => {
+                ExprKind::Func(name.name().to_string(), args.iter().map(|arg| to_expr(arg, span)).collect())
+            }
+            items => ExprKind::List(items.iter().map(|item| to_expr(item, span)).collect()),
+        },
+        Val::Vector(items) => ExprKind::Vector(items.iter().map(|item| to_expr(item, span)).collect()),
+        Val::Map(map) => ExprKind::Map(map.iter().map(|(k, v)| (to_expr(k, span), to_expr(v, span))).collect()),
+        Val::Set(set) => ExprKind::Set(set.iter().map(|item| to_expr(item, span)).collect()),
+        _ => ExprKind::Atom(val.clone()),
+    };
+    Expr { kind, span: span.clone() }
+}
+
+pub fn try_parse_expr(src: &str) -> Result<Expr> {
+    read_expr(&Source::new("<input>", src))
+}
+
+pub fn read_expr(source: &Arc<Source>) -> Result<Expr> {
+    let tokens = tokenize(source)?;
+    let mut reader = Reader { tokens: &tokens, pos: 0, end: Span::end_of(source) };
+
+    let expr = reader.read_expr()?;
+    reader.skip_datum_comments()?;
+    if let Some((_, span)) = tokens.get(reader.pos) {
+        return Err(Error::parse("unexpected input after expression", &span.to(&reader.end)));
     }
-    lazy_static! {
-        pub static ref SYM: Regex = Regex::new(r"^[a-zA-Z_]+[0-9a-zA-Z_]*$").unwrap();
+    Ok(expr)
+}
+
+pub fn parse_program(src: &str) -> Result<Vec<Expr>> {
+    read_program(&Source::new("<input>", src))
+}
+
+// every top-level form in `source`, in order
+pub fn read_program(source: &Arc<Source>) -> Result<Vec<Expr>> {
+    let tokens = tokenize(source)?;
+    let mut reader = Reader { tokens: &tokens, pos: 0, end: Span::end_of(source) };
+
+    let mut exprs = vec![];
+    reader.skip_datum_comments()?;
+    while reader.peek().is_some() {
+        exprs.push(reader.read_expr()?);
+        reader.skip_datum_comments()?;
     }
-    lazy_static! {
-        pub static ref SEP_ARGS: Regex = Regex::new(r"(.*)\(.*\)(.*)").unwrap();
-    }
-}
-
-pub fn try_parse_atom(src: &str) -> anyhow::Result<Val> {
-    use Val::*;
-    use regexes::*;
-
-    if INT.is_match(src) {
-        Ok( Int(i64::from_str(src)?) )
-    } else if FLOAT.is_match(src) {
-        Ok( Float(f64::from_str(src)?) )
-    } else if STR.is_match(src) {
-        Ok(
-            Str(
-                src
-                .chars()
-                .skip(1)
-                .take(src.len() - 2)
-                .collect::<String>()
-                )
-           )
-    } else if LIST.is_match(src) {
-        todo!()
-    } else {
-        Err(anyhow::Error::msg(format!("malformed value: `{}`", src)))
-    }
-}
-
-pub fn try_parse_expr(src: &str) -> anyhow::Result<Expr> {
-    use Expr::*;
-    use regexes::*;
-
-    if SYM.is_match(src) {
-        Ok(Sym(String::from(src)))
-    } else if FUNC.is_match(src) {
-        let mat = FUNC.captures(src).unwrap();
-        let func_name = String::from(&mat["funcname"]);
-
-        let mut args = vec![];
-
-        if src.chars().skip(1).collect::<String>().contains("(") {
-            // until first function
-            let (before, nested_after) = &mat["args"].split_once("(").unwrap();
-            let (nested, after) = nested_after.rsplit_once(")").unwrap();
-    
-            let before_args = before
-                .split(" ")
-                .filter(|s| !s.is_empty())
-                .map(str::trim)
-                .collect::<Vec<_>>();
-            let after_args = after
-                .split(" ")
-                .filter(|s| !s.is_empty())
-                .map(str::trim)
-                .collect::<Vec<_>>();
-    
-            for arg in before_args {
-                args.push(try_parse_expr(arg)?);
+    Ok(exprs)
+}
+
+struct Reader<'a> {
+    tokens: &'a [(Token, Span)],
+    pos: usize,
+    end: Span,
+}
+impl<'a> Reader<'a> {
+    fn next(&mut self) -> Option<&'a (Token, Span)> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&'a (Token, Span)> {
+        self.tokens.get(self.pos)
+    }
+
+    // drops the forms commented out with `#;`
+    fn skip_datum_comments(&mut self) -> Result<()> {
+        while let Some((Token::DatumComment, _)) = self.peek() {
+            self.pos += 1;
+            self.read_expr()?;
+        }
+        Ok(())
+    }
+
+    fn read_expr(&mut self) -> Result<Expr> {
+        use ExprKind::*;
+
+        self.skip_datum_comments()?;
+        let Some((token, span)) = self.next() else {
+            return Err(Error::incomplete("unexpected end of input", &self.end));
+        };
+        let kind = match token {
+            Token::LParen => return self.read_form(span),
+            Token::LBrace => {
+                let (items, span) = self.read_items(span, &Token::RBrace, "{")?;
+                if items.len() % 2 != 0 {
+                    return Err(Error::parse("map literal expects an even number of forms", &span));
+                }
+                let mut items = items.into_iter();
+                let mut entries = vec![];
+                while let (Some(k), Some(v)) = (items.next(), items.next()) {
+                    entries.push((k, v));
+                }
+                return Ok(Expr { kind: Map(entries), span });
             }
-            args.push(try_parse_expr(
-                    &(String::from("(") + nested + ")")
-                    )?);
-            for arg in after_args {
-                args.push(try_parse_expr(arg)?);
+            Token::HashBrace => {
+                let (items, span) = self.read_items(span, &Token::RBrace, "#{")?;
+                return Ok(Expr { kind: Set(items), span });
             }
-        } else {
-            let raw_args = &mat["args"]
-                .split(" ")
-                .into_iter()
-                .filter(|s| !s.is_empty())
-                .map(str::trim)
-                .collect::<Vec<_>>();
-            for arg in raw_args {
-                args.push(try_parse_expr(arg)?);
+            Token::LBracket => {
+                let (items, span) = self.read_items(span, &Token::RBracket, "[")?;
+                return Ok(Expr { kind: Vector(items), span });
             }
-        }
+            Token::RParen => return Err(Error::parse("unexpected `)`", span)),
+            Token::RBrace => return Err(Error::parse("unexpected `}`", span)),
+            Token::RBracket => return Err(Error::parse("unexpected `]`", span)),
+            Token::DatumComment => unreachable!(),
+            Token::Quote => {
+                let quoted = self.read_expr()?;
+                return Ok(Expr { kind: Atom(quote(&quoted)), span: span.to(&quoted.span) });
+            }
+            // `` `x ``, `,x` and `,@x` are short for `(quasiquote x)`, `(unquote x)` and `(unquote-splicing x)`
+            Token::Quasiquote | Token::Unquote | Token::UnquoteSplicing => {
+                let name = match token {
+                    Token::Quasiquote => "quasiquote",
+                    Token::Unquote => "unquote",
+                    _ => "unquote-splicing",
+                };
+                let quoted = self.read_expr()?;
+                let span = span.to(&quoted.span);
+                return Ok(Expr { kind: Func(String::from(name), vec![quoted]), span });
+            }
+            Token::Int(i) => Atom(Val::Int(*i)),
+            Token::Big(big) => Atom(Val::Big(big.clone())),
+            Token::Ratio(ratio) => Atom(Val::Ratio(ratio.clone())),
+            Token::Float(f) => Atom(Val::Float(*f)),
+            Token::Char(c) => Atom(Val::Char(*c)),
+            Token::Str(s) => Atom(Val::Str(s.clone())),
+            Token::Bool(b) => Atom(Val::Bool(*b)),
+            Token::Nil => Atom(Val::Nil),
+            Token::Keyword(k) => Atom(Val::Keyword(Symbol::new(k))),
+            Token::Sym(s) => Sym(s.clone()),
+        };
+        Ok(Expr { kind, span: span.clone() })
+    }
 
-        Ok(Func(func_name, args))
-    } else {
-        Ok(Atom(try_parse_atom(src)?))
+    // called after the opening paren has been consumed
+    fn read_form(&mut self, open: &Span) -> Result<Expr> {
+        let (items, span) = self.read_items(open, &Token::RParen, "(")?;
+        let kind = match items.first() {
+            Some(Expr { kind: ExprKind::Sym(_), .. }) => {
+                let mut items = items.into_iter();
+                let Some(Expr { kind: ExprKind::Sym(name), .. }) = items.next() else { unreachable!() };
+                ExprKind::Func(name, items.collect())
+            }
+            _ => ExprKind::List(items),
+        };
+        Ok(Expr { kind, span })
+    }
+
+    // the forms up to `close`, and the span from `open` to it
+    fn read_items(&mut self, open: &Span, close: &Token, name: &str) -> Result<(Vec<Expr>, Span)> {
+        let mut items = vec![];
+        loop {
+            self.skip_datum_comments()?;
+            match self.peek() {
+                Some((token, span)) if token == close => {
+                    self.pos += 1;
+                    return Ok((items, open.to(span)));
+                }
+                Some(_) => items.push(self.read_expr()?),
+                None => return Err(Error::incomplete(format!("unclosed `{}`", name), open)),
+            }
+        }
     }
 }
 
@@ -130,7 +440,7 @@ mod tests {
     fn test_parse_atom_number() {
         use Val::*;
 
-        let input = vec!["1", "99", "39019272", "0", "89328.32378", "0.0"];
+        let input = ["1", "99", "39019272", "0", "89328.32378", "0.0"];
         let expected = vec![
             Int(1), 
             Int(99), 
@@ -141,67 +451,416 @@ mod tests {
         ];
 
         let result = input.iter()
-            .map(|s| try_parse_atom(*s).unwrap())
+            .map(|s| try_parse_atom(s).unwrap())
             .collect::<Vec<_>>();
 
         assert_eq!(expected, result);
+
+        let input = [
+            ("-5", Int(-5)),
+            ("+3", Int(3)),
+            ("-0", Int(0)),
+            ("1_000_000", Int(1000000)),
+            ("-9223372036854775808", Int(i64::MIN)),
+            ("0x1F", Int(31)),
+            ("-0xff", Int(-255)),
+            ("0XdEaD_bEeF", Int(0xdeadbeef)),
+            ("0b1010", Int(10)),
+            ("+0B1_0000", Int(16)),
+            ("1e10", Float(1e10)),
+            ("1E-2", Float(0.01)),
+            ("-2.5e+3", Float(-2500.0)),
+            (".5", Float(0.5)),
+            ("-.5", Float(-0.5)),
+            ("1.", Float(1.0)),
+            ("1_000.000_1", Float(1000.0001)),
+            ("inf", Float(f64::INFINITY)),
+            ("-inf", Float(f64::NEG_INFINITY)),
+            ("+inf.0", Float(f64::INFINITY)),
+            ("nan", Float(f64::NAN)),
+            ("-nan.0", Float(f64::NAN)),
+            ("-6/3", Int(-2)),
+        ];
+        for (src, expected) in input {
+            assert_eq!(try_parse_atom(src).unwrap(), expected, "`{}`", src);
+        }
+        assert_eq!(try_parse_atom("-1/2").unwrap(), Val::from(Rational::new(BigInt::from(-1), BigInt::from(2))));
+        assert!(matches!(try_parse_atom("0x1_0000_0000_0000_0000").unwrap(), Big(_)));
+        assert!(matches!(try_parse_atom("-99_999_999_999_999_999_999").unwrap(), Big(_)));
+
+        let input = [
+            "007", "-01", "1__0", "_1", "1_", "1._5", "0x", "0xg", "0b2", "0b_1", "1e", "1e+", "e5", ".", "-.", "+.e1",
+            "1.2.3", "1/", "/2", "1/02", "1/0", "1.5/2", "0x1/2", "infinity", "inf.5", "nan.00", "1f",
+        ];
+        for src in input {
+            assert!(try_parse_atom(src).is_err(), "`{}` should not parse", src);
+        }
+
+        // signs and points are still symbols on their own
+        for src in ["-", "+", "...", "-x", "+inf-ity"] {
+            assert!(regexes::SYM.is_match(src), "`{}` should be a symbol", src);
+        }
+    }
+
+    #[test]
+    fn test_parse_atom_literals() {
+        use Val::*;
+
+        let input = ["#t", "#f", "#true", "#false", "true", "false", "nil"];
+        let result = input.iter()
+            .map(|s| try_parse_atom(s).unwrap())
+            .collect::<Vec<_>>();
+        let expected = vec![Bool(true), Bool(false), Bool(true), Bool(false), Bool(true), Bool(false), Nil];
+        assert_eq!(result, expected);
+
+        let result = try_parse_expr("(f #t nil truthy)").unwrap().kind;
+        let expected = ExprKind::Func(
+            String::from("f"),
+            vec![ExprKind::Atom(Bool(true)).into(), ExprKind::Atom(Nil).into(), ExprKind::Sym(String::from("truthy")).into()],
+        );
+        assert_eq!(result, expected);
+
+        let result = try_parse_atom(":key-word").unwrap();
+        assert_eq!(result, Keyword(Symbol::new("key-word")));
+        assert_ne!(result, Val::Sym(Symbol::new("key-word")));
+
+        assert_eq!(try_parse_atom(r"#\space").unwrap(), Char(' '));
+        assert_eq!(try_parse_atom(r"#\x41").unwrap(), Char('A'));
+
+        for src in ["#tru", "#x", "nill", ":", "::a", r"#\", r"#\a b"] {
+            assert!(try_parse_atom(src).is_err(), "`{}` should not parse", src);
+        }
     }
 
     #[test]
     fn test_parse_atom_string() {
         use Val::Str;
 
-        let input = vec![r#""ilahids89090""#, r#""some string \"quoted string\"""#];
+        let input = [r#""ilahids89090""#, r#""some string \"quoted string\"""#, r#""日本語 \u{1F600}""#, "\"a\nb\""];
         let result = input.iter()
-            .map(|s| try_parse_atom(*s).unwrap())
+            .map(|s| try_parse_atom(s).unwrap())
             .collect::<Vec<_>>();
         let expected = vec![
             Str(String::from("ilahids89090")), 
-            Str(String::from(r#"some string \"quoted string\""#))
+            Str(String::from(r#"some string "quoted string""#)),
+            Str(String::from("日本語 😀")),
+            Str(String::from("a\nb")),
+        ];
+        assert_eq!(result, expected);
+
+        assert!(try_parse_atom(r#""a" "b""#).is_err());
+        assert!(try_parse_atom(r#""a\""#).is_err());
+    }
+
+    #[test]
+    fn test_parse_atom_list() {
+        use Val::*;
+
+        let input = [r#"'(1 2.0 "x" '(3))"#, "'()", "'(() (1 (2)))", "'(f x)"];
+        let result = input.iter()
+            .map(|s| try_parse_atom(s).unwrap())
+            .collect::<Vec<_>>();
+        let expected = vec![
+            List(vec![Int(1), Float(2.0), Str(String::from("x")), List(vec![Sym(Symbol::new("quote")), List(vec![Int(3)].into())].into())].into()),
+            List(vec![].into()),
+            List(vec![List(vec![].into()), List(vec![Int(1), List(vec![Int(2)].into())].into())].into()),
+            List(vec![Sym(Symbol::new("f")), Sym(Symbol::new("x"))].into()),
         ];
         assert_eq!(result, expected);
+
+        assert!(try_parse_atom("'(1 2").is_err());
     }
 
     #[test]
     fn test_parse_expr_sym() {
-        use Expr::Sym;
+        use ExprKind::Sym;
 
-        let input = vec!["AuhLahdsd_93089", "_90380293____dlauhdkS"];
+        let input = ["AuhLahdsd_93089", "_90380293____dlauhdkS", "+", "<=", "-", "set!", "a->b", "..."];
         let result = input.iter()
-            .map(|s| try_parse_expr(*s).unwrap())
+            .map(|s| try_parse_expr(s).unwrap().kind)
             .collect::<Vec<_>>();
         let expected = vec![
             Sym(String::from("AuhLahdsd_93089")),
-            Sym(String::from("_90380293____dlauhdkS"))
+            Sym(String::from("_90380293____dlauhdkS")),
+            Sym(String::from("+")),
+            Sym(String::from("<=")),
+            Sym(String::from("-")),
+            Sym(String::from("set!")),
+            Sym(String::from("a->b")),
+            Sym(String::from("...")),
         ];
         assert_eq!(result, expected);
     }
 
     #[test]
     fn test_parse_expr_func() {
-        use Expr::*;
-        use Val::*;
+        use ExprKind::*;
+        use Val::{Float, Int};
 
-        let input = vec![
+        let input = [
             "(dlhadk_90898 980890 0.0 jlhdksd)", 
             "(___idojldi980_ jkd (defhkh dsakdj))"
         ];
         let result = input.iter()
-            .map(|s| try_parse_expr(*s).unwrap())
+            .map(|s| try_parse_expr(s).unwrap().kind)
             .collect::<Vec<_>>();
         let expected = vec![
             Func(
                 String::from("dlhadk_90898"), 
-                vec![ Atom(Int(980890)), Atom(Float(0.0)), Sym(String::from("jlhdksd")) ]
+                vec![ Atom(Int(980890)).into(), Atom(Float(0.0)).into(), Sym(String::from("jlhdksd")).into() ]
                 ),
             Func(
                 String::from("___idojldi980_"),
                 vec![ 
-                    Sym(String::from("jkd")),
-                    Func(String::from("defhkh"), vec![Sym(String::from("dsakdj"))])
+                    Sym(String::from("jkd")).into(),
+                    Func(String::from("defhkh"), vec![Sym(String::from("dsakdj")).into()]).into()
                     ]
                 )
         ];
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_parse_expr_nested_siblings() {
+        use ExprKind::*;
+        use Val::{Int, Str};
+
+        let result = try_parse_expr(r#"(f (a) (b "x y" (c 1)) 2)"#).unwrap().kind;
+        let expected = Func(
+            String::from("f"),
+            vec![
+                Func(String::from("a"), vec![]).into(),
+                Func(
+                    String::from("b"),
+                    vec![
+                        Atom(Str(String::from("x y"))).into(),
+                        Func(String::from("c"), vec![Atom(Int(1)).into()]).into(),
+                    ],
+                ).into(),
+                Atom(Int(2)).into(),
+            ],
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_expr_list() {
+        use ExprKind::*;
+        use Val::Int;
+
+        let input = ["()", "((f) 1)"];
+        let result = input.iter()
+            .map(|s| try_parse_expr(s).unwrap().kind)
+            .collect::<Vec<_>>();
+        let expected = vec![
+            List(vec![]),
+            List(vec![Func(String::from("f"), vec![]).into(), Atom(Int(1)).into()]),
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_expr_malformed() {
+        let input = ["(f (a)", "(f))", "(f) (g)", ")", ""];
+        for src in input {
+            assert!(try_parse_expr(src).is_err(), "`{}` should not parse", src);
+        }
+    }
+
+    #[test]
+    fn test_parse_expr_spans() {
+        let expr = try_parse_expr("(f 'x `(y ,@z))").unwrap();
+        let ExprKind::Func(_, args) = &expr.kind else { panic!() };
+        let spans = args.iter().map(|arg| (arg.span.start, arg.span.end)).collect::<Vec<_>>();
+        assert_eq!(spans, vec![(3, 5), (6, 14)]);
+
+        let expr = try_parse_expr("(f\n  (g 1))").unwrap();
+        let ExprKind::Func(_, args) = &expr.kind else { panic!() };
+        assert_eq!((expr.span.start, expr.span.end), (0, 11));
+        assert_eq!((args[0].span.line, args[0].span.column, args[0].span.start, args[0].span.end), (2, 3, 5, 10));
+
+        let input = [
+            ("(f (a)", "unclosed `(`", 1, true),
+            ("(f))", "unexpected input after expression", 4, false),
+            ("(f", "unclosed `(`", 1, true),
+            ("(f \"a", "unterminated string", 4, true),
+        ];
+        for (src, message, column, incomplete) in input {
+            let err = try_parse_expr(src).unwrap_err();
+            assert!(matches!(err.kind(), crate::ErrorKind::ParseError { incomplete: i, .. } if *i == incomplete), "`{}`", src);
+            assert_eq!((err.to_string().as_str(), err.span().column), (message, column));
+        }
+    }
+
+    #[test]
+    fn test_parse_quasiquote() {
+        use ExprKind::*;
+
+        let sym = |name: &str| Expr::from(Sym(String::from(name)));
+        let wrap = |name: &str, expr: Expr| Expr::from(Func(String::from(name), vec![expr]));
+        let result = try_parse_expr("`(a ,b ,@(c))").unwrap();
+        let expected = wrap(
+            "quasiquote",
+            Func(
+                String::from("a"),
+                vec![wrap("unquote", sym("b")), wrap("unquote-splicing", Func(String::from("c"), vec![]).into())],
+            )
+            .into(),
+        );
+        assert_eq!(result, expected);
+
+        assert!(try_parse_expr("`").is_err());
+        assert!(try_parse_expr("(a ,)").is_err());
+    }
+
+    #[test]
+    fn test_parse_maps_and_sets() {
+        use ExprKind::*;
+
+        let key = |name: &str| Expr::from(Atom(Val::Keyword(Symbol::new(name))));
+        let result = try_parse_expr("{:a 1 :b (f)}").unwrap();
+        let expected = Map(vec![
+            (key("a"), Atom(Val::Int(1)).into()),
+            (key("b"), Func(String::from("f"), vec![]).into()),
+        ]);
+        assert_eq!(result, expected.into());
+
+        let result = try_parse_expr("#{1 #{}}").unwrap();
+        let expected = Set(vec![Atom(Val::Int(1)).into(), Set(vec![]).into()]);
+        assert_eq!(result, expected.into());
+
+        let input = [("{:a}", "map literal expects an even number of forms"), ("{:a 1", "unclosed `{`"), ("#{1)", "unexpected `)`")];
+        for (src, message) in input {
+            let err = try_parse_expr(src).unwrap_err();
+            assert!(err.to_string().contains(message), "`{}`: {}", src, err);
+        }
+        assert!(try_parse_expr("}").is_err());
+    }
+
+    #[test]
+    fn test_parse_vectors() {
+        use ExprKind::*;
+
+        let result = try_parse_expr("[a [1] (f)]").unwrap();
+        let expected = Vector(vec![
+            Sym(String::from("a")).into(),
+            Vector(vec![Atom(Val::Int(1)).into()]).into(),
+            Func(String::from("f"), vec![]).into(),
+        ]);
+        assert_eq!(result, expected.into());
+
+        // a quoted vector is data, and quoting it again keeps it that way
+        let result = try_parse_expr("'[a 1]").unwrap();
+        let expected = Atom(Val::Vector(vec![Val::Sym(Symbol::new("a")), Val::Int(1)]));
+        assert_eq!(result, expected.into());
+        assert_eq!(to_expr(&quote(&result), &Span::default()), result);
+
+        assert!(try_parse_expr("[1").is_err());
+        assert!(try_parse_expr("[1)").is_err());
+    }
+
+    #[test]
+    fn test_val_ordering() {
+        use Val::*;
+
+        // values of different types never compare equal, and every value equals itself
+        assert_ne!(Int(1), Float(1.0));
+        assert_eq!(Float(f64::NAN), Float(f64::NAN));
+        assert!(Int(2) < Int(10));
+        assert!(Str(String::from("a")) < Str(String::from("b")));
+        assert!(List(vec![Int(1)].into()) < List(vec![Int(1), Int(0)].into()));
+
+        let set = [Int(1), Float(1.0), Int(1), Nil].into_iter().collect::<BTreeSet<_>>();
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let input = [
+            "nil", "#t", "#f", "0", "-42", "99999999999999999999", "-1/3", "1.0", "-0.0", "1e21", "1.5e-7", "inf",
+            "-inf", "nan", r"#\a", r"#\space", r"#\(", r"#\λ", r#""a \"quoted\" \\ string\n\t""#, "sym", ":key",
+            "()", "(1 (2 3) x)", "(quote x)", "[1 [2] ()]", "{:a 1 \"b\" [2]}", "#{1 #{2} :c}", "{}", "#{}",
+        ];
+        for src in input {
+            let val = quote(&try_parse_expr(src).unwrap());
+            let printed = val.to_string();
+            assert_eq!(quote(&try_parse_expr(&printed).unwrap()), val, "`{}` printed as `{}`", src, printed);
+        }
+
+        let input = [
+            (Val::Float(1.0), "1.0"),
+            (Val::Char(' '), r"#\space"),
+            (Val::Char('\u{7}'), r"#\alarm"),
+            (Val::Char('\u{a0}'), r"#\xa0"),
+            (Val::Str(String::from("\u{1b}x")), r#""\u{1b}x""#),
+            (quote(&try_parse_expr("(f 'x {:a [1]})").unwrap()), "(f (quote x) {:a [1]})"),
+        ];
+        for (val, expected) in input {
+            assert_eq!(val.to_string(), expected);
+        }
+        let printed = crate::System::new().eval(&try_parse_expr("(lambda (x) x)").unwrap()).unwrap().to_string();
+        assert!(printed.starts_with("#<") && try_parse_expr(&printed).is_err());
+
+        // code prints as it's written
+        let input = ["(f 'x '(1 y) \"s\" [a b] {:k v} #{1})", "((lambda (x) x) 1)", "'[1]", "'{:a 1}", "`(a ,b ,@c)"];
+        for src in input {
+            let expr = try_parse_expr(src).unwrap();
+            assert_eq!(try_parse_expr(&expr.to_string()).unwrap(), expr, "`{}` printed as `{}`", src, expr);
+        }
+        assert_eq!(try_parse_expr("( f  'x\n[1] )").unwrap().to_string(), "(f 'x [1])");
+    }
+
+    #[test]
+    fn test_quote_round_trip() {
+        let input = ["(f x '(1 y) (g))", "((lambda (x) x) 1)", "'sym", "\"s\"", "{:a (f x)}", "#{1 (f)}", "'{:a x}", "'#{x}"];
+        for src in input {
+            let expr = try_parse_expr(src).unwrap();
+            assert_eq!(to_expr(&quote(&expr), &Span::default()), expr, "`{}`", src);
+        }
+    }
+
+    #[test]
+    fn test_parse_program() {
+        use ExprKind::*;
+        use Val::Int;
+
+        let result = parse_program("\n  (f 1)\n\n x\t'(2)  (g)\n")
+            .unwrap()
+            .into_iter()
+            .map(|expr| expr.kind)
+            .collect::<Vec<_>>();
+        let expected = vec![
+            Func(String::from("f"), vec![Atom(Int(1)).into()]),
+            Sym(String::from("x")),
+            Atom(Val::List(vec![Int(2)].into())),
+            Func(String::from("g"), vec![]),
+        ];
+        assert_eq!(result, expected);
+
+        assert_eq!(parse_program("  \n ").unwrap(), vec![]);
+        assert!(parse_program("(f 1) (g").is_err());
+        assert!(parse_program("(f 1))").is_err());
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        use ExprKind::*;
+        use Val::Int;
+
+        let src = "; setup\n#;(ignored 1)\n(f 1 #;2 #;#;3 4 #| (g) |# 5) ; done\n'(#;x 6) #;(h)";
+        let result = parse_program(src)
+            .unwrap()
+            .into_iter()
+            .map(|expr| expr.kind)
+            .collect::<Vec<_>>();
+        let expected = vec![
+            Func(String::from("f"), vec![Atom(Int(1)).into(), Atom(Int(5)).into()]),
+            Atom(Val::List(vec![Int(6)].into())),
+        ];
+        assert_eq!(result, expected);
+
+        assert_eq!(try_parse_expr("(f) #;(g)").unwrap().kind, Func(String::from("f"), vec![]));
+        assert!(try_parse_expr("(f #;)").is_err());
+        assert!(try_parse_expr("#;(f)").is_err());
+    }
 }
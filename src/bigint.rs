@@ -0,0 +1,506 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Shl, Shr, Sub};
+
+// an integer of any size: a sign, and the magnitude in base 2^32 with the least significant digit
+// first. There are never leading zero digits, so zero has no digits and is never negative.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn zero() -> Self {
+        BigInt { negative: false, digits: vec![] }
+    }
+
+    fn from_parts(negative: bool, mut digits: Vec<u32>) -> Self {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        BigInt { negative: negative && !digits.is_empty(), digits }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn abs(&self) -> Self {
+        BigInt { negative: false, digits: self.digits.clone() }
+    }
+
+    // the magnitude, if it fits in 64 bits
+    fn magnitude_u64(&self) -> Option<u64> {
+        match self.digits.as_slice() {
+            [] => Some(0),
+            [low] => Some(*low as u64),
+            [low, high] => Some((*high as u64) << 32 | *low as u64),
+            _ => None,
+        }
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        let magnitude = self.magnitude_u64()?;
+        if self.negative {
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+
+    // the number of bits in the magnitude, which is 0 for zero
+    pub fn bit_len(&self) -> u64 {
+        match self.digits.last() {
+            Some(top) => self.digits.len() as u64 * 32 - top.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    // the nearest float, or an infinity if it's too big for one. Only the top 64 bits and whether
+    // any bit below them is set matter, so the conversion of those to a float is the only rounding.
+    pub fn to_f64(&self) -> f64 {
+        let shift = self.bit_len().saturating_sub(64);
+        let top = (self >> shift).magnitude_u64().unwrap();
+        let (whole, bits) = ((shift / 32) as usize, shift % 32);
+        let sticky = self.digits[..whole].iter().any(|digit| *digit != 0)
+            || self.digits.get(whole).is_some_and(|digit| digit & ((1 << bits) - 1) != 0);
+        // the lowest of the 64 bits is far below a float's precision, so it can stand in for the rest.
+        // Shifts this big overflow either way.
+        let magnitude = (top | sticky as u64) as f64 * 2f64.powi(shift.min(2048) as i32);
+        if self.negative { -magnitude } else { magnitude }
+    }
+
+    // digits in `radix` with an optional sign, and nothing else
+    pub fn from_str_radix(src: &str, radix: u32) -> Option<Self> {
+        let (negative, src) = match src.as_bytes().first() {
+            Some(b'-') => (true, &src[1..]),
+            Some(b'+') => (false, &src[1..]),
+            _ => (false, src),
+        };
+        if src.is_empty() {
+            return None;
+        }
+        let mut digits = vec![];
+        for c in src.chars() {
+            mul_add_small(&mut digits, radix, c.to_digit(radix)?);
+        }
+        Some(BigInt::from_parts(negative, digits))
+    }
+
+    pub fn pow(&self, mut exp: u32) -> Self {
+        let mut result = BigInt::from(1);
+        let mut base = self.clone();
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = &result * &base;
+            }
+            base = &base * &base;
+            exp >>= 1;
+        }
+        result
+    }
+
+    // the quotient rounded towards zero and the remainder, which takes the sign of `self`. Panics
+    // if `other` is zero.
+    pub fn div_rem(&self, other: &Self) -> (Self, Self) {
+        assert!(!other.is_zero(), "BigInt division by zero");
+        let (quotient, remainder) = div_rem_magnitude(&self.digits, &other.digits);
+        (
+            BigInt::from_parts(self.negative != other.negative, quotient),
+            BigInt::from_parts(self.negative, remainder),
+        )
+    }
+
+    // never negative, and only zero if both are
+    pub fn gcd(&self, other: &Self) -> Self {
+        let (mut a, mut b) = (self.abs(), other.abs());
+        while !b.is_zero() {
+            let (_, remainder) = a.div_rem(&b);
+            (a, b) = (b, remainder);
+        }
+        a
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(i: i64) -> Self {
+        let magnitude = i.unsigned_abs();
+        BigInt::from_parts(i < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+fn mul_add_small(digits: &mut Vec<u32>, factor: u32, addend: u32) {
+    let mut carry = addend as u64;
+    for digit in digits.iter_mut() {
+        let product = *digit as u64 * factor as u64 + carry;
+        *digit = product as u32;
+        carry = product >> 32;
+    }
+    if carry > 0 {
+        digits.push(carry as u32);
+    }
+}
+
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut sum = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0;
+    for (i, digit) in long.iter().enumerate() {
+        let total = *digit as u64 + *short.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push(total as u32);
+        carry = total >> 32;
+    }
+    if carry > 0 {
+        sum.push(carry as u32);
+    }
+    sum
+}
+
+// `a` must be at least `b`
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, digit) in a.iter().enumerate() {
+        let (partial, under1) = digit.overflowing_sub(*b.get(i).unwrap_or(&0));
+        let (partial, under2) = partial.overflowing_sub(borrow);
+        difference.push(partial);
+        borrow = (under1 || under2) as u32;
+    }
+    difference
+}
+
+fn mul_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut product = vec![0u32; a.len() + b.len()];
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, y) in b.iter().enumerate() {
+            let total = *x as u64 * *y as u64 + product[i + j] as u64 + carry;
+            product[i + j] = total as u32;
+            carry = total >> 32;
+        }
+        product[i + b.len()] = carry as u32;
+    }
+    product
+}
+
+fn div_rem_small(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0; a.len()];
+    let mut remainder = 0u64;
+    for (i, digit) in a.iter().enumerate().rev() {
+        let current = remainder << 32 | *digit as u64;
+        quotient[i] = (current / divisor as u64) as u32;
+        remainder = current % divisor as u64;
+    }
+    (quotient, remainder as u32)
+}
+
+// schoolbook long division a digit at a time, Knuth's algorithm D (TAOCP vol. 2, 4.3.1)
+fn div_rem_magnitude(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if let [divisor] = b {
+        let (quotient, remainder) = div_rem_small(a, *divisor);
+        return (quotient, vec![remainder]);
+    }
+    if cmp_magnitude(a, b).is_lt() {
+        return (vec![], a.to_vec());
+    }
+
+    // shifting both so the divisor's top bit is set keeps each estimated quotient digit at most 2
+    // too big
+    let shift = b[b.len() - 1].leading_zeros();
+    let mut v = shl_bits(b, shift);
+    v.pop();
+    let mut u = shl_bits(a, shift);
+    let n = v.len();
+    let (top, next) = (v[n - 1] as u64, v[n - 2] as u64);
+
+    let mut quotient = vec![0u32; a.len() - n + 1];
+    for j in (0..quotient.len()).rev() {
+        // estimate the digit from the top two digits of the remainder and the divisor
+        let high = (u[j + n] as u64) << 32 | u[j + n - 1] as u64;
+        let (mut qhat, mut rhat) = (high / top, high % top);
+        while qhat >> 32 != 0 || qhat * next > (rhat << 32 | u[j + n - 2] as u64) {
+            qhat -= 1;
+            rhat += top;
+            if rhat >> 32 != 0 {
+                break;
+            }
+        }
+
+        // subtract `qhat * v` from the window of the remainder ending at `j + n`
+        let (mut borrow, mut carry) = (0i64, 0u64);
+        for i in 0..n {
+            let product = qhat * v[i] as u64 + carry;
+            carry = product >> 32;
+            let difference = u[i + j] as i64 - borrow - (product as u32) as i64;
+            u[i + j] = difference as u32;
+            borrow = (difference < 0) as i64;
+        }
+        let difference = u[j + n] as i64 - borrow - carry as i64;
+        u[j + n] = difference as u32;
+
+        // the estimate was still one too big, so add one `v` back
+        if difference < 0 {
+            qhat -= 1;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let sum = u[i + j] as u64 + v[i] as u64 + carry;
+                u[i + j] = sum as u32;
+                carry = sum >> 32;
+            }
+            u[j + n] = u[j + n].wrapping_add(carry as u32);
+        }
+        quotient[j] = qhat as u32;
+    }
+
+    let remainder = (0..n)
+        .map(|i| ((u[i + 1] as u64) << 32 | u[i] as u64) >> shift)
+        .map(|digit| digit as u32)
+        .collect();
+    (quotient, remainder)
+}
+
+// the digits shifted left by fewer than 32 bits, with one more digit for what's shifted out
+fn shl_bits(digits: &[u32], shift: u32) -> Vec<u32> {
+    let mut shifted = Vec::with_capacity(digits.len() + 1);
+    let mut carry = 0;
+    for digit in digits {
+        let wide = (*digit as u64) << shift;
+        shifted.push(wide as u32 | carry);
+        carry = (wide >> 32) as u32;
+    }
+    shifted.push(carry);
+    shifted
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::from_parts(self.negative, add_magnitude(&self.digits, &other.digits));
+        }
+        match cmp_magnitude(&self.digits, &other.digits) {
+            Ordering::Less => BigInt::from_parts(other.negative, sub_magnitude(&other.digits, &self.digits)),
+            _ => BigInt::from_parts(self.negative, sub_magnitude(&self.digits, &other.digits)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::from_parts(self.negative != other.negative, mul_magnitude(&self.digits, &other.digits))
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.digits.clone())
+    }
+}
+
+// multiplies the magnitude by `2^bits`
+impl Shl<u64> for &BigInt {
+    type Output = BigInt;
+
+    fn shl(self, bits: u64) -> BigInt {
+        let mut digits = vec![0; (bits / 32) as usize];
+        digits.extend(shl_bits(&self.digits, (bits % 32) as u32));
+        BigInt::from_parts(self.negative, digits)
+    }
+}
+
+// drops the low `bits` bits of the magnitude, so it rounds towards zero
+impl Shr<u64> for &BigInt {
+    type Output = BigInt;
+
+    fn shr(self, bits: u64) -> BigInt {
+        let (skip, bits) = ((bits / 32) as usize, bits % 32);
+        let digits = self.digits.get(skip..).unwrap_or(&[]);
+        let shifted = (0..digits.len())
+            .map(|i| {
+                let high = digits.get(i + 1).map_or(0, |high| (*high as u64) << 32);
+                ((high | digits[i] as u64) >> bits) as u32
+            })
+            .collect();
+        BigInt::from_parts(self.negative, shifted)
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.digits, &other.digits),
+            (true, true) => cmp_magnitude(&other.digits, &self.digits),
+        }
+    }
+}
+
+// in decimal
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // nine decimal digits at a time, least significant first
+        let mut chunks = vec![];
+        let mut digits = self.digits.clone();
+        while !digits.is_empty() {
+            let (quotient, chunk) = div_rem_small(&digits, 1_000_000_000);
+            chunks.push(chunk);
+            digits = quotient;
+            while digits.last() == Some(&0) {
+                digits.pop();
+            }
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+impl fmt::Debug for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(src: &str) -> BigInt {
+        BigInt::from_str_radix(src, 10).unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = big("123456789012345678901234567890");
+        let b = big("-987654321098765432109876543210");
+        assert_eq!((&a + &b).to_string(), "-864197532086419753208641975320");
+        assert_eq!((&a - &b).to_string(), "1111111110111111111011111111100");
+        assert_eq!((&a * &b).to_string(), "-121932631137021795226185032733622923332237463801111263526900");
+        assert_eq!((&a - &a), BigInt::zero());
+        assert!(!(&a - &a).is_negative());
+
+        let (quotient, remainder) = b.div_rem(&a);
+        assert_eq!((quotient.to_string(), remainder.to_string()), (String::from("-8"), String::from("-9000000000900000000090")));
+        let (quotient, remainder) = a.div_rem(&BigInt::from(7));
+        assert_eq!(&(&quotient * &BigInt::from(7)) + &remainder, a);
+
+        let a = &big("2").pow(128) - &BigInt::from(1);
+        let (quotient, remainder) = a.div_rem(&(&big("2").pow(64) + &BigInt::from(1)));
+        assert_eq!((quotient.to_string(), remainder), (String::from("18446744073709551615"), BigInt::zero()));
+
+        assert_eq!(big("2").pow(100).to_string(), "1267650600228229401496703205376");
+        assert_eq!(big("12").gcd(&big("-18")), BigInt::from(6));
+        assert!(b < a && big("-1") < BigInt::zero());
+    }
+
+    fn xorshift(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    // up to `max_len` digits, mostly near 0 and 2^32 - 1 where the estimated quotient digits are
+    // most often wrong
+    fn random(state: &mut u64, max_len: u64) -> BigInt {
+        let digits = (0..xorshift(state) % (max_len + 1))
+            .map(|_| match xorshift(state) % 4 {
+                0 => 0,
+                1 => u32::MAX,
+                2 => u32::MAX - (xorshift(state) % 4) as u32,
+                _ => xorshift(state) as u32,
+            })
+            .collect();
+        BigInt::from_parts(xorshift(state) & 1 == 0, digits)
+    }
+
+    #[test]
+    fn test_div_rem() {
+        let mut state = 0x2545f4914f6cdd1d;
+        for _ in 0..5000 {
+            let (a, b) = (random(&mut state, 12), random(&mut state, 6));
+            if b.is_zero() {
+                continue;
+            }
+            let (quotient, remainder) = a.div_rem(&b);
+            assert_eq!(&(&quotient * &b) + &remainder, a, "{} / {}", a, b);
+            assert!(cmp_magnitude(&remainder.digits, &b.digits).is_lt(), "{} / {}", a, b);
+            assert!(remainder.is_zero() || remainder.is_negative() == a.is_negative(), "{} / {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_div_rem_performance() {
+        // a bit at a time, this took seconds even in a release build
+        let start = std::time::Instant::now();
+        let (a, b) = (big("3").pow(20000), big("7").pow(5000));
+        for _ in 0..10 {
+            let (quotient, remainder) = a.div_rem(&b);
+            assert_eq!(&(&quotient * &b) + &remainder, a);
+        }
+        assert!(start.elapsed().as_secs() < 5, "division took {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn test_conversions() {
+        for i in [0, 1, -1, i64::MAX, i64::MIN, 1 << 32] {
+            let big = BigInt::from(i);
+            assert_eq!(big.to_i64(), Some(i));
+            assert_eq!(big.to_string(), i.to_string());
+        }
+        assert_eq!((&BigInt::from(i64::MAX) + &BigInt::from(1)).to_i64(), None);
+        assert_eq!((&BigInt::from(i64::MIN) - &BigInt::from(1)).to_i64(), None);
+
+        assert_eq!(BigInt::from_str_radix("ff", 16), Some(BigInt::from(255)));
+        assert_eq!(BigInt::from_str_radix("-0", 10), Some(BigInt::zero()));
+        assert_eq!(BigInt::from_str_radix("12a", 10), None);
+        assert_eq!(BigInt::from_str_radix("-", 10), None);
+        assert_eq!(big("1000000000000000000000").to_f64(), 1e21);
+        // 2^95 + 2^42 + 1 is just over halfway between two floats, and rounding the top 64 bits on
+        // their own would make it exactly halfway and round it down
+        let f = &(&big("2").pow(95) + &big("2").pow(42)) + &BigInt::from(1);
+        assert_eq!(f.to_f64(), 2f64.powi(95) + 2f64.powi(43));
+        assert_eq!((-&f).to_f64(), -(2f64.powi(95) + 2f64.powi(43)));
+        assert_eq!(BigInt::from(10).pow(400).to_f64(), f64::INFINITY);
+
+        assert_eq!((big("0").bit_len(), big("-255").bit_len(), BigInt::from(1 << 32).bit_len()), (0, 8, 33));
+        assert_eq!(&big("-1000000000000") >> 8, big("-3906250000"));
+        assert_eq!(&BigInt::from(1 << 40) >> 40, BigInt::from(1));
+        assert_eq!(&big("5") >> 64, BigInt::zero());
+    }
+}
@@ -0,0 +1,871 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ast::{quote, to_expr, Val::{self, *}};
+use crate::error::{Error, Result};
+use crate::number::{to_rational, Rational};
+use crate::span::Span;
+use crate::symbol::Symbol;
+use crate::{macros, Arity, BuiltinFn, System};
+
+pub fn install(sys: &mut System) {
+    let builtins: [(&str, Arity, BuiltinFn); 40] = [
+        ("+", Arity::AtLeast(0), add),
+        ("-", Arity::AtLeast(1), sub),
+        ("*", Arity::AtLeast(0), mul),
+        ("/", Arity::AtLeast(1), div),
+        ("mod", Arity::Exact(2), modulo),
+        ("<", Arity::AtLeast(1), lt),
+        ("<=", Arity::AtLeast(1), le),
+        (">", Arity::AtLeast(1), gt),
+        (">=", Arity::AtLeast(1), ge),
+        ("=", Arity::AtLeast(1), num_eq),
+        ("exact->inexact", Arity::Exact(1), exact_to_inexact),
+        ("inexact->exact", Arity::Exact(1), inexact_to_exact),
+        ("apply", Arity::Exact(2), apply),
+        ("map", Arity::Exact(2), map),
+        ("macroexpand-1", Arity::Exact(1), macroexpand_1),
+        ("macroexpand", Arity::Exact(1), macroexpand),
+        ("raise", Arity::Exact(1), raise),
+        ("symbol->string", Arity::Exact(1), symbol_to_string),
+        ("string->symbol", Arity::Exact(1), string_to_symbol),
+        ("gensym", Arity::Range(0, 1), gensym),
+        ("char->integer", Arity::Exact(1), char_to_integer),
+        ("integer->char", Arity::Exact(1), integer_to_char),
+        ("char-upcase", Arity::Exact(1), char_upcase),
+        ("string-ref", Arity::Exact(2), string_ref),
+        ("get", Arity::Range(2, 3), get),
+        ("assoc", Arity::AtLeast(3), assoc),
+        ("dissoc", Arity::AtLeast(1), dissoc),
+        ("keys", Arity::Exact(1), keys),
+        ("vals", Arity::Exact(1), vals),
+        ("contains?", Arity::Exact(2), contains),
+        ("merge", Arity::AtLeast(0), merge),
+        ("union", Arity::AtLeast(1), union),
+        ("intersection", Arity::AtLeast(1), intersection),
+        ("difference", Arity::AtLeast(1), difference),
+        ("cons", Arity::Exact(2), cons),
+        ("car", Arity::Exact(1), car),
+        ("cdr", Arity::Exact(1), cdr),
+        ("nth", Arity::Exact(2), nth),
+        ("conj", Arity::AtLeast(1), conj),
+        ("subvec", Arity::Range(2, 3), subvec),
+    ];
+    for (name, arity, func) in builtins {
+        sys.define_builtin(name, arity, func).unwrap();
+    }
+}
+
+fn type_error(name: &str, val: &Val) -> Error {
+    Error::type_error(name, "numbers", val)
+}
+
+fn to_float(name: &str, val: &Val) -> Result<f64> {
+    match val {
+        Int(i) => Ok(*i as f64),
+        Big(big) => Ok(big.to_f64()),
+        Ratio(ratio) => Ok(ratio.to_f64()),
+        Float(f) => Ok(*f),
+        _ => Err(type_error(name, val)),
+    }
+}
+
+fn to_exact(name: &str, val: &Val) -> Result<Rational> {
+    to_rational(val).ok_or_else(|| type_error(name, val))
+}
+
+// the operations on each kind of number that make up an arithmetic builtin. `int` is the fast path
+// for two `Int`s, and returns `None` when the answer isn't an `Int`
+struct NumOp {
+    int: fn(i64, i64) -> Option<i64>,
+    exact: fn(&Rational, &Rational) -> Rational,
+    float: fn(f64, f64) -> f64,
+}
+
+const ADD: NumOp = NumOp { int: i64::checked_add, exact: |a, b| a + b, float: |a, b| a + b };
+const SUB: NumOp = NumOp { int: i64::checked_sub, exact: |a, b| a - b, float: |a, b| a - b };
+const MUL: NumOp = NumOp { int: i64::checked_mul, exact: |a, b| a * b, float: |a, b| a * b };
+
+// exact numbers stay exact, growing into bignums and fractions as needed, and anything involving a
+// Float is a Float
+fn binary(name: &str, a: &Val, b: &Val, op: &NumOp) -> Result<Val> {
+    match (a, b) {
+        (Int(x), Int(y)) if let Some(result) = (op.int)(*x, *y) => Ok(Int(result)),
+        (Float(_), _) | (_, Float(_)) => Ok(Float((op.float)(to_float(name, a)?, to_float(name, b)?))),
+        _ => Ok(Val::from((op.exact)(&to_exact(name, a)?, &to_exact(name, b)?))),
+    }
+}
+
+fn fold(name: &str, init: Val, args: &[Val], op: &NumOp) -> Result<Val> {
+    args.iter()
+        .try_fold(init, |acc, arg| binary(name, &acc, arg, op))
+}
+
+fn add(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    fold("+", Int(0), &args, &ADD)
+}
+
+fn sub(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    match args.split_first() {
+        Some((only, [])) => binary("-", &Int(0), only, &SUB),
+        Some((first, rest)) => fold("-", first.clone(), rest, &SUB),
+        None => unreachable!(),
+    }
+}
+
+fn mul(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    fold("*", Int(1), &args, &MUL)
+}
+
+// exact divisors are checked exactly, since a tiny fraction can round to a zero float
+fn check_divisor(name: &str, val: &Val) -> Result<()> {
+    let is_zero = match val {
+        Float(f) => *f == 0.0,
+        _ => to_exact(name, val)?.is_zero(),
+    };
+    if is_zero {
+        Err(Error::divide_by_zero(name))
+    } else {
+        Ok(())
+    }
+}
+
+fn div(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    let (init, rest) = match args.split_first() {
+        Some((only, [])) => (Int(1), std::slice::from_ref(only)),
+        Some((first, rest)) => (first.clone(), rest),
+        None => unreachable!(),
+    };
+
+    // dividing exact numbers gives a fraction unless it comes out whole
+    let op = NumOp {
+        int: |a, b| if a.checked_rem(b)? == 0 { a.checked_div(b) } else { None },
+        exact: |a, b| a / b,
+        float: |a, b| a / b,
+    };
+    rest.iter().try_fold(init, |acc, arg| {
+        check_divisor("/", arg)?;
+        binary("/", &acc, arg, &op)
+    })
+}
+
+fn modulo(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    check_divisor("mod", &args[1])?;
+    // the result takes the sign of the divisor
+    let op = NumOp {
+        int: |a, b| {
+            let rem = a.checked_rem(b)?;
+            if rem != 0 && (rem < 0) != (b < 0) { rem.checked_add(b) } else { Some(rem) }
+        },
+        exact: |a, b| a - &(b * &Rational::from((a / b).floor())),
+        float: |a, b| a - b * (a / b).floor(),
+    };
+    binary("mod", &args[0], &args[1], &op)
+}
+
+fn compare(name: &str, a: &Val, b: &Val) -> Result<Option<Ordering>> {
+    match (a, b) {
+        (Int(a), Int(b)) => Ok(Some(a.cmp(b))),
+        (Float(a), Float(b)) => Ok(a.partial_cmp(b)),
+        (Float(f), exact) => Ok(compare_exact(name, exact, *f)?.map(Ordering::reverse)),
+        (exact, Float(f)) => compare_exact(name, exact, *f),
+        _ => Ok(Some(to_exact(name, a)?.cmp(&to_exact(name, b)?))),
+    }
+}
+
+// compares against the float's exact value, so nothing is lost rounding `exact` to a float
+fn compare_exact(name: &str, exact: &Val, f: f64) -> Result<Option<Ordering>> {
+    let exact = to_exact(name, exact)?;
+    Ok(match Rational::from_f64(f) {
+        Some(f) => Some(exact.cmp(&f)),
+        None if f.is_nan() => None,
+        None if f > 0.0 => Some(Ordering::Less),
+        None => Some(Ordering::Greater),
+    })
+}
+
+fn exact_to_inexact(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    Ok(Float(to_float("exact->inexact", &args[0])?))
+}
+
+// the exact value of a float, which for something like `0.1` is the fraction closest to it
+fn inexact_to_exact(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    match &args[0] {
+        Float(f) => Rational::from_f64(*f)
+            .map(Val::from)
+            .ok_or_else(|| Error::invalid_argument("inexact->exact", format!("has no exact value for `{}`", f))),
+        val => to_exact("inexact->exact", val).map(Val::from),
+    }
+}
+
+fn chain(name: &str, args: &[Val], pred: fn(Ordering) -> bool) -> Result<Val> {
+    let mut result = true;
+    for pair in args.windows(2) {
+        // keep going after a false comparison so every argument is type checked
+        result &= compare(name, &pair[0], &pair[1])?.is_some_and(pred);
+    }
+    if let [only] = args {
+        to_float(name, only)?;
+    }
+    Ok(Bool(result))
+}
+
+fn lt(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    chain("<", &args, Ordering::is_lt)
+}
+
+fn le(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    chain("<=", &args, Ordering::is_le)
+}
+
+fn gt(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    chain(">", &args, Ordering::is_gt)
+}
+
+fn ge(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    chain(">=", &args, Ordering::is_ge)
+}
+
+fn num_eq(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    chain("=", &args, Ordering::is_eq)
+}
+
+fn expect_list(name: &str, val: Val) -> Result<Vec<Val>> {
+    match val {
+        List(items) => Ok(items.to_vec()),
+        _ => Err(Error::type_error(name, "a list", &val)),
+    }
+}
+
+// `(apply f '(1 2))` calls `(f 1 2)`
+fn apply(sys: &mut System, mut args: Vec<Val>) -> Result<Val> {
+    let list = expect_list("apply", args.pop().unwrap())?;
+    sys.apply(&args[0], list)
+}
+
+fn map(sys: &mut System, mut args: Vec<Val>) -> Result<Val> {
+    let list = expect_list("map", args.pop().unwrap())?;
+    let mapped = list
+        .into_iter()
+        .map(|item| sys.apply(&args[0], vec![item]))
+        .collect::<Result<_>>()?;
+    Ok(List(mapped))
+}
+
+fn symbol_to_string(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    match &args[0] {
+        Sym(sym) => Ok(Str(sym.name().to_string())),
+        val => Err(Error::type_error("symbol->string", "a symbol", val)),
+    }
+}
+
+fn string_to_symbol(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    match &args[0] {
+        Str(name) => Ok(Sym(Symbol::new(name))),
+        val => Err(Error::type_error("string->symbol", "a string", val)),
+    }
+}
+
+// `(gensym)` or `(gensym "prefix")`, a symbol that's different from every other, for macros to
+// bind names the code they're given can't refer to
+fn gensym(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    match args.as_slice() {
+        [] => Ok(Sym(Symbol::gensym("g"))),
+        [Str(prefix)] => Ok(Sym(Symbol::gensym(prefix))),
+        [val] => Err(Error::type_error("gensym", "a string", val)),
+        _ => unreachable!(),
+    }
+}
+
+fn expect_char(name: &str, val: &Val) -> Result<char> {
+    match val {
+        Char(c) => Ok(*c),
+        _ => Err(Error::type_error(name, "a character", val)),
+    }
+}
+
+fn char_to_integer(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    Ok(Int(expect_char("char->integer", &args[0])? as i64))
+}
+
+// only Unicode scalar values are characters, so surrogates are rejected
+fn integer_to_char(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    match &args[0] {
+        Int(i) => u32::try_from(*i)
+            .ok()
+            .and_then(char::from_u32)
+            .map(Char)
+            .ok_or_else(|| Error::invalid_argument("integer->char", format!("got `{}`, which isn't a Unicode scalar value", i))),
+        val => Err(Error::type_error("integer->char", "an integer", val)),
+    }
+}
+
+// characters whose uppercase is more than one character, like `ß`, are left alone
+fn char_upcase(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    let c = expect_char("char-upcase", &args[0])?;
+    let mut upper = c.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(upper), None) => Ok(Char(upper)),
+        _ => Ok(Char(c)),
+    }
+}
+
+// indexes by character rather than byte, so it's O(n)
+fn string_ref(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    let Str(s) = &args[0] else {
+        return Err(Error::type_error("string-ref", "a string", &args[0]));
+    };
+    let i = expect_index("string-ref", &args[1], usize::MAX)?;
+    s.chars()
+        .nth(i)
+        .map(Char)
+        .ok_or_else(|| Error::index_out_of_range("string-ref", i as i64))
+}
+
+fn expect_map(name: &str, val: Val) -> Result<BTreeMap<Val, Val>> {
+    match val {
+        Map(map) => Ok(map),
+        _ => Err(Error::type_error(name, "a map", &val)),
+    }
+}
+
+fn expect_set(name: &str, val: Val) -> Result<BTreeSet<Val>> {
+    match val {
+        Set(set) => Ok(set),
+        _ => Err(Error::type_error(name, "a set", &val)),
+    }
+}
+
+// `(get coll key default)`, where `default` is `nil` if it's left out. A set maps its members to
+// themselves, and a vector its indices to its items.
+fn get(_: &mut System, mut args: Vec<Val>) -> Result<Val> {
+    let default = if args.len() == 3 { args.pop().unwrap() } else { Nil };
+    let found = match (&args[0], &args[1]) {
+        (Map(map), key) => map.get(key),
+        (Set(set), key) => set.get(key),
+        (Vector(vector), Int(i)) => usize::try_from(*i).ok().and_then(|i| vector.get(i)),
+        (Vector(_), _) => None,
+        (val, _) => return Err(Error::type_error("get", "a map, a set or a vector", val)),
+    };
+    Ok(found.cloned().unwrap_or(default))
+}
+
+// `(assoc map k v ...)` is `map` with each key set to the value after it. For a vector the keys are
+// indices, and the index one past the end appends.
+fn assoc(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    let mut args = args.into_iter();
+    let coll = args.next().unwrap();
+    let rest = args.collect::<Vec<_>>();
+    if rest.len() % 2 != 0 {
+        return Err(Error::invalid_argument("assoc", "expects keys and values in pairs"));
+    }
+    match coll {
+        Map(mut map) => {
+            for pair in rest.chunks(2) {
+                map.insert(pair[0].clone(), pair[1].clone());
+            }
+            Ok(Map(map))
+        }
+        Vector(mut vector) => {
+            for pair in rest.chunks(2) {
+                match expect_index("assoc", &pair[0], vector.len() + 1)? {
+                    i if i == vector.len() => vector.push(pair[1].clone()),
+                    i => vector[i] = pair[1].clone(),
+                }
+            }
+            Ok(Vector(vector))
+        }
+        val => Err(Error::type_error("assoc", "a map or a vector", &val)),
+    }
+}
+
+fn dissoc(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    let mut args = args.into_iter();
+    let mut map = expect_map("dissoc", args.next().unwrap())?;
+    for key in args {
+        map.remove(&key);
+    }
+    Ok(Map(map))
+}
+
+// in key order, as are `vals`
+fn keys(_: &mut System, mut args: Vec<Val>) -> Result<Val> {
+    let map = expect_map("keys", args.pop().unwrap())?;
+    Ok(List(map.into_keys().collect()))
+}
+
+fn vals(_: &mut System, mut args: Vec<Val>) -> Result<Val> {
+    let map = expect_map("vals", args.pop().unwrap())?;
+    Ok(List(map.into_values().collect()))
+}
+
+fn contains(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    match &args[0] {
+        Map(map) => Ok(Bool(map.contains_key(&args[1]))),
+        Set(set) => Ok(Bool(set.contains(&args[1]))),
+        val => Err(Error::type_error("contains?", "a map or a set", val)),
+    }
+}
+
+// later maps win when they share a key
+fn merge(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    let mut merged = BTreeMap::new();
+    for arg in args {
+        merged.extend(expect_map("merge", arg)?);
+    }
+    Ok(Map(merged))
+}
+
+fn union(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    let mut union = BTreeSet::new();
+    for arg in args {
+        union.extend(expect_set("union", arg)?);
+    }
+    Ok(Set(union))
+}
+
+fn intersection(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    let mut args = args.into_iter();
+    let mut intersection = expect_set("intersection", args.next().unwrap())?;
+    for arg in args {
+        let set = expect_set("intersection", arg)?;
+        intersection.retain(|item| set.contains(item));
+    }
+    Ok(Set(intersection))
+}
+
+// the members of the first set that aren't in any of the others
+fn difference(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    let mut args = args.into_iter();
+    let mut difference = expect_set("difference", args.next().unwrap())?;
+    for arg in args {
+        let set = expect_set("difference", arg)?;
+        difference.retain(|item| !set.contains(item));
+    }
+    Ok(Set(difference))
+}
+
+// an index below `len`
+fn expect_index(name: &str, val: &Val, len: usize) -> Result<usize> {
+    match val {
+        Int(i) => usize::try_from(*i)
+            .ok()
+            .filter(|i| *i < len)
+            .ok_or_else(|| Error::index_out_of_range(name, *i)),
+        _ => Err(Error::type_error(name, "an integer index", val)),
+    }
+}
+
+// `(cons x list)` is `list` with `x` in front, sharing `list` rather than copying it
+fn cons(_: &mut System, mut args: Vec<Val>) -> Result<Val> {
+    match args.pop().unwrap() {
+        List(list) => Ok(List(list.cons(args.pop().unwrap()))),
+        val => Err(Error::type_error("cons", "a list", &val)),
+    }
+}
+
+fn car(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    match &args[0] {
+        List(list) if !list.is_empty() => Ok(list.car().unwrap().clone()),
+        val => Err(Error::type_error("car", "a non-empty list", val)),
+    }
+}
+
+fn cdr(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    match &args[0] {
+        List(list) if !list.is_empty() => Ok(List(list.cdr().unwrap().clone())),
+        val => Err(Error::type_error("cdr", "a non-empty list", val)),
+    }
+}
+
+// O(1) for vectors, O(n) for lists
+fn nth(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    match &args[0] {
+        Vector(vector) => Ok(vector[expect_index("nth", &args[1], vector.len())?].clone()),
+        List(list) => {
+            let i = expect_index("nth", &args[1], usize::MAX)?;
+            list.iter()
+                .nth(i)
+                .cloned()
+                .ok_or_else(|| Error::index_out_of_range("nth", i as i64))
+        }
+        val => Err(Error::type_error("nth", "a list or a vector", val)),
+    }
+}
+
+// adds items where it's cheapest: the end of a vector, or the front of a list
+fn conj(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    let mut args = args.into_iter();
+    match args.next().unwrap() {
+        Vector(mut vector) => {
+            vector.extend(args);
+            Ok(Vector(vector))
+        }
+        List(list) => Ok(List(args.fold(list, |list, item| list.cons(item)))),
+        Set(mut set) => {
+            set.extend(args);
+            Ok(Set(set))
+        }
+        val => Err(Error::type_error("conj", "a list, a vector or a set", &val)),
+    }
+}
+
+// `(subvec v start end)`, where `end` is the length of `v` if it's left out
+fn subvec(_: &mut System, args: Vec<Val>) -> Result<Val> {
+    let Vector(vector) = &args[0] else {
+        return Err(Error::type_error("subvec", "a vector", &args[0]));
+    };
+    let end = match args.get(2) {
+        Some(end) => expect_index("subvec", end, vector.len() + 1)?,
+        None => vector.len(),
+    };
+    let start = expect_index("subvec", &args[1], end + 1)?;
+    Ok(Vector(vector[start..end].to_vec()))
+}
+
+// `(macroexpand-1 '(m x))` is the code that `(m x)` expands to
+fn macroexpand_1(sys: &mut System, args: Vec<Val>) -> Result<Val> {
+    let expr = to_expr(&args[0], &Span::default());
+    match macros::expand_1(sys, &expr)? {
+        Some(expanded) => Ok(quote(&expanded)),
+        None => Ok(args[0].clone()),
+    }
+}
+
+fn macroexpand(sys: &mut System, args: Vec<Val>) -> Result<Val> {
+    let expr = to_expr(&args[0], &Span::default());
+    Ok(quote(&macros::expand(sys, &expr)?))
+}
+
+// `(raise val)` fails with `val`, which is the error message if it's a string
+fn raise(_: &mut System, mut args: Vec<Val>) -> Result<Val> {
+    Err(Error::raised(args.pop().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{parse_program, try_parse_atom, try_parse_expr};
+    use crate::error::ErrorKind;
+
+    fn eval(src: &str) -> Result<Val> {
+        System::new().eval(&try_parse_expr(src)?)
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let input = [
+            "(+)", "(+ 1 2 3)", "(- 5)", "(- 10 1 2)", "(* 2 3 4)", "(/ 8 2)",
+            "(+ 1 0.5)", "(* 2 1.5)", "(/ 1.0 4)", "(mod 7 3)", "(mod (- 7) 3)", "(mod 7 (- 3))",
+            "(mod 5.5 2)", "(- (* 3 (+ 1 2)) (/ 9 3))",
+        ];
+        let result = input.iter()
+            .map(|s| eval(s).unwrap())
+            .collect::<Vec<_>>();
+        let expected = vec![
+            Int(0), Int(6), Int(-5), Int(7), Int(24), Int(4),
+            Float(1.5), Float(3.0), Float(0.25), Int(1), Int(2), Int(-2),
+            Float(1.5), Int(6),
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_numeric_tower() {
+        let num = |src: &str| try_parse_atom(src).unwrap();
+        let input = [
+            // overflow promotes to a bignum, and results that fit go back to being an `Int`
+            ("(+ 9223372036854775807 1)", "9223372036854775808"),
+            ("(- (- 0 9223372036854775807) 2)", "-9223372036854775809"),
+            ("(* 99999999999999999999 99999999999999999999)", "9999999999999999999800000000000000000001"),
+            ("(- 99999999999999999999 99999999999999999998)", "1"),
+            ("(/ 99999999999999999998 2)", "49999999999999999999"),
+            ("(/ (- 0 9223372036854775807 1) (- 1))", "9223372036854775808"),
+            ("(mod 99999999999999999999 10)", "9"),
+            // dividing exact numbers is exact
+            ("(/ 7 2)", "7/2"),
+            ("(/ 1 3)", "1/3"),
+            ("(+ 1/3 1/6)", "1/2"),
+            ("(+ 1/3 2/3)", "1"),
+            ("(* 2/3 3/4)", "1/2"),
+            ("(- 1/2)", "-1/2"),
+            ("(/ 1/2)", "2"),
+            ("(/ 6/4 3)", "1/2"),
+            ("(mod 7/2 2)", "3/2"),
+            ("(mod (- 7/2) 2)", "1/2"),
+            ("(* 1/10 3)", "3/10"),
+            ("(+ 1/2 0.25)", "0.75"),
+            ("(exact->inexact 1/4)", "0.25"),
+            ("(exact->inexact 3)", "3.0"),
+            ("(inexact->exact 0.5)", "1/2"),
+            ("(inexact->exact 2.0)", "2"),
+            ("(inexact->exact 1/3)", "1/3"),
+            ("(inexact->exact 0.1)", "3602879701896397/36028797018963968"),
+        ];
+        for (src, expected) in input {
+            assert_eq!(eval(src).unwrap(), num(expected), "`{}`", src);
+        }
+
+        let input = [
+            ("(< 1/3 1/2 1 99999999999999999999)", Bool(true)),
+            ("(= 1/2 0.5)", Bool(true)),
+            ("(= 2/4 1/2)", Bool(true)),
+            ("(> 1/3 0.3)", Bool(true)),
+            ("(= (+ 1/10 2/10) 3/10)", Bool(true)),
+            ("(= (+ 0.1 0.2) 0.3)", Bool(false)),
+            ("(= 1/10 0.1)", Bool(false)),
+            ("(< 99999999999999999999 inf)", Bool(true)),
+            ("(< 1 nan)", Bool(false)),
+        ];
+        for (src, expected) in input {
+            assert_eq!(eval(src).unwrap(), expected, "`{}`", src);
+        }
+
+        // a fraction whose parts are too big for floats
+        let huge = format!("1{}", "0".repeat(400));
+        let almost_one = format!("{}/{}1", huge, &huge[..400]);
+        assert_eq!(eval(&format!("(exact->inexact {})", almost_one)).unwrap(), Float(1.0));
+        assert_eq!(eval(&format!("(< {} 1.0)", almost_one)).unwrap(), Bool(true));
+        assert_eq!(eval(&format!("(> {} 1e300)", huge)).unwrap(), Bool(true));
+        assert!(matches!(num("99999999999999999999"), Big(_)));
+        assert!(matches!(num("4/2"), Int(2)));
+        assert!(try_parse_atom("1/0").is_err());
+
+        for src in ["(inexact->exact (/ 1.0 0.0))", "(exact->inexact \"1\")", "(mod 1/2 0)"] {
+            assert!(eval(src).is_err(), "`{}` should fail", src);
+        }
+    }
+
+    #[test]
+    fn test_comparison() {
+        let input = [
+            "(< 1 2 3)", "(< 1 3 2)", "(<= 1 1 2)", "(> 3 2.5)", "(>= 2 2.0)", "(= 1 1.0)",
+            "(= 1 2)", "(< 1)",
+        ];
+        let result = input.iter()
+            .map(|s| eval(s).unwrap())
+            .collect::<Vec<_>>();
+        let expected = vec![
+            Bool(true), Bool(false), Bool(true), Bool(true), Bool(true), Bool(true),
+            Bool(false), Bool(true),
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_arithmetic_errors() {
+        let input = [
+            "(/ 1 0)",
+            "(/ 1/2 0)",
+            "(/ 1.5 0.0)",
+            "(mod 1 0)",
+            r#"(+ 1 "2")"#,
+            r#"(< 1 "2")"#,
+            r#"(< "2")"#,
+        ];
+        for src in input {
+            assert!(eval(src).is_err(), "`{}` should fail", src);
+        }
+        assert_eq!(eval("(/ 4 0)").unwrap_err().to_string(), "division by zero in `/`");
+        // a fraction too small for a float is still not zero
+        let tiny = format!("1/1{}", "0".repeat(400));
+        assert_eq!(eval(&format!("(/ 1 {})", tiny)).unwrap().to_string(), format!("1{}", "0".repeat(400)));
+        assert_eq!(eval(&format!("(mod 1 {})", tiny)).unwrap(), Int(0));
+        assert!(matches!(eval("(mod 4 0)").unwrap_err().kind(), ErrorKind::DivideByZero { func, .. } if func == "mod"));
+        assert!(matches!(
+            eval(r#"(+ 1 "2")"#).unwrap_err().kind(),
+            ErrorKind::TypeError { func, got: Str(s), .. } if func == "+" && s == "2"
+        ));
+    }
+
+    #[test]
+    fn test_raise() {
+        let err = eval("(raise 'oops)").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::UserRaised { val: Sym(sym), .. } if sym.name() == "oops"));
+        assert_eq!(eval(r#"(raise "file not found")"#).unwrap_err().to_string(), "file not found");
+        assert_eq!(eval("(raise [1 2])").unwrap_err().to_string(), "raised `[1 2]`");
+        // nothing after the raise runs
+        let err = eval("(let ((x (raise 1))) (car x))").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::UserRaised { val: Int(1), .. }));
+        assert_eq!(err.span().column, 10);
+    }
+
+    #[test]
+    fn test_higher_order() {
+        let input = [
+            ("(apply + '(1 2 3))", Int(6)),
+            ("(apply (lambda (x y) (- x y)) '(5 3))", Int(2)),
+            ("(map (lambda (x) (* x x)) '(1 2 3))", List(vec![Int(1), Int(4), Int(9)].into())),
+            ("(map - '())", List(vec![].into())),
+            ("(let ((n 10)) (map (lambda (x) (+ x n)) '(1 2)))", List(vec![Int(11), Int(12)].into())),
+        ];
+        for (src, expected) in input {
+            assert_eq!(eval(src).unwrap(), expected, "`{}`", src);
+        }
+
+        for src in ["(apply + 1)", "(map 1 '(1))", "(map (lambda (x y) x) '(1))"] {
+            assert!(eval(src).is_err(), "`{}` should fail", src);
+        }
+    }
+
+    #[test]
+    fn test_symbols() {
+        let input = [
+            ("(symbol->string 'abc)", Str(String::from("abc"))),
+            (r#"(string->symbol "abc")"#, Sym(Symbol::new("abc"))),
+            (":key", Keyword(Symbol::new("key"))),
+            ("'(:a b)", List(vec![Keyword(Symbol::new("a")), Sym(Symbol::new("b"))].into())),
+        ];
+        for (src, expected) in input {
+            assert_eq!(eval(src).unwrap(), expected, "`{}`", src);
+        }
+        assert_eq!(eval(r#"(string->symbol "abc")"#).unwrap(), eval("'abc").unwrap());
+
+        let Sym(g1) = eval("(gensym)").unwrap() else { panic!() };
+        let Sym(g2) = eval(r#"(gensym "tmp")"#).unwrap() else { panic!() };
+        assert_ne!(g1, g2);
+        assert!(g2.name().starts_with("tmp"));
+        // gensyms aren't interned, so reading back the printed name gives a different symbol
+        let src = "(define g (gensym)) (get {g 1} (string->symbol (symbol->string g)) :missing)";
+        assert_eq!(System::new().eval_program(&parse_program(src).unwrap()).unwrap(), Keyword(Symbol::new("missing")));
+
+        // a gensym can't capture a name the user wrote
+        let src = "
+            (defmacro with-temp (x body) (let ((tmp (gensym))) `(let ((,tmp ,x)) ,body)))
+            (define tmp 5)
+            (with-temp 1 tmp)";
+        assert_eq!(System::new().eval_program(&parse_program(src).unwrap()).unwrap(), Int(5));
+
+        for src in ["(symbol->string :key)", r#"(symbol->string "abc")"#, "(string->symbol 'abc)", "(gensym 1)", r#"(gensym "a" "b")"#] {
+            assert!(eval(src).is_err(), "`{}` should fail", src);
+        }
+    }
+
+    #[test]
+    fn test_maps() {
+        let map = |entries: &[(&str, Val)]| {
+            Map(entries.iter().map(|(k, v)| (Keyword(Symbol::new(k)), v.clone())).collect())
+        };
+        let input = [
+            ("{:a (+ 1 2) :b 2}", map(&[("a", Int(3)), ("b", Int(2))])),
+            ("{}", map(&[])),
+            ("(get {:a 1} :a)", Int(1)),
+            ("(get {:a 1} :b)", Nil),
+            ("(get {:a 1} :b 0)", Int(0)),
+            ("(get {1 :int 1.0 :float} 1.0)", Keyword(Symbol::new("float"))),
+            ("(get {'(1 2) 3} '(1 2))", Int(3)),
+            ("(assoc {:a 1} :b 2 :a 3)", map(&[("a", Int(3)), ("b", Int(2))])),
+            ("(dissoc {:a 1 :b 2} :a :c)", map(&[("b", Int(2))])),
+            ("(keys {:b 2 :a 1})", List(vec![Keyword(Symbol::new("a")), Keyword(Symbol::new("b"))].into())),
+            ("(vals {:b 2 :a 1})", List(vec![Int(1), Int(2)].into())),
+            ("(contains? {:a nil} :a)", Bool(true)),
+            ("(contains? {:a 1} :b)", Bool(false)),
+            ("(merge {:a 1 :b 1} {:b 2} {})", map(&[("a", Int(1)), ("b", Int(2))])),
+            ("(= 1 (get {{:k 1} 1} {:k 1}))", Bool(true)),
+        ];
+        for (src, expected) in input {
+            assert_eq!(eval(src).unwrap(), expected, "`{}`", src);
+        }
+
+        for src in ["{:a}", "(get '(1) 1)", "(assoc {} :a)", "(keys #{1})", "(merge {} 1)", "(get {} 1 2 3)"] {
+            assert!(eval(src).is_err(), "`{}` should fail", src);
+        }
+    }
+
+    #[test]
+    fn test_sets() {
+        let set = |items: &[i64]| Set(items.iter().map(|i| Int(*i)).collect());
+        let input = [
+            ("#{3 1 (+ 1 1) 1}", set(&[1, 2, 3])),
+            ("(contains? #{1 2} 2)", Bool(true)),
+            ("(contains? #{1 2} 2.0)", Bool(false)),
+            ("(get #{1 2} 1)", Int(1)),
+            ("(union #{1 2} #{2 3} #{})", set(&[1, 2, 3])),
+            ("(intersection #{1 2 3} #{2 3 4} #{3 2})", set(&[2, 3])),
+            ("(difference #{1 2 3} #{2} #{3})", set(&[1])),
+            // `nan` equals itself as a member, even though `(= nan nan)` is false
+            ("(contains? #{nan} nan)", Bool(true)),
+            ("(= nan nan)", Bool(false)),
+        ];
+        for (src, expected) in input {
+            assert_eq!(eval(src).unwrap(), expected, "`{}`", src);
+        }
+
+        for src in ["(union #{1} {})", "(intersection '(1))", "(difference)"] {
+            assert!(eval(src).is_err(), "`{}` should fail", src);
+        }
+    }
+
+    #[test]
+    fn test_lists_and_vectors() {
+        let list = |items: &[i64]| List(items.iter().map(|i| Int(*i)).collect());
+        let vector = |items: &[i64]| Vector(items.iter().map(|i| Int(*i)).collect());
+        let input = [
+            ("(cons 1 '(2 3))", list(&[1, 2, 3])),
+            ("(cons 1 '())", list(&[1])),
+            ("(car '(1 2))", Int(1)),
+            ("(cdr '(1 2))", list(&[2])),
+            ("(cdr '(1))", list(&[])),
+            ("(let ((l '(2 3))) (= (car (cdr (cons 1 l))) (car l)))", Bool(true)),
+            ("[1 (+ 1 1) 3]", vector(&[1, 2, 3])),
+            ("(nth [1 2 3] 1)", Int(2)),
+            ("(nth '(1 2 3) 2)", Int(3)),
+            ("(conj [1 2] 3 4)", vector(&[1, 2, 3, 4])),
+            ("(conj '(1 2) 3 4)", list(&[4, 3, 1, 2])),
+            ("(conj #{1} 2)", Set([Int(1), Int(2)].into_iter().collect())),
+            ("(subvec [1 2 3 4] 1 3)", vector(&[2, 3])),
+            ("(subvec [1 2 3 4] 2)", vector(&[3, 4])),
+            ("(subvec [1 2] 2 2)", vector(&[])),
+            ("(assoc [1 2] 0 5 2 6)", vector(&[5, 2, 6])),
+            ("(get [1 2] 1)", Int(2)),
+            ("(get [1 2] 2 :none)", Keyword(Symbol::new("none"))),
+            ("(get [1 2] -1)", Nil),
+            // a vector and a list with the same items are still different values
+            ("(get {[1] :v} '(1))", Nil),
+            (
+                "(letrec ((build (lambda (n acc) (if (= n 0) acc (build (- n 1) (cons n acc)))))) (nth (build 10000 '()) 9999))",
+                Int(10000),
+            ),
+        ];
+        for (src, expected) in input {
+            assert_eq!(eval(src).unwrap(), expected, "`{}`", src);
+        }
+
+        let input = [
+            "(car '())", "(cdr '())", "(cons 1 [2])", "(car [1])", "(nth [1 2] 2)", "(nth '(1) 1)", "(nth [1] -1)",
+            "(nth [1] 1.0)", "(subvec [1 2] 2 1)", "(subvec [1 2] 0 3)", "(assoc [1] 2 0)", "(conj {} 1)",
+        ];
+        for src in input {
+            assert!(eval(src).is_err(), "`{}` should fail", src);
+        }
+    }
+
+    #[test]
+    fn test_chars() {
+        let input = [
+            (r"#\a", Char('a')),
+            (r"(char->integer #\A)", Int(65)),
+            (r"(char->integer #\λ)", Int(0x3bb)),
+            ("(integer->char 955)", Char('λ')),
+            ("(integer->char 0x1F600)", Char('😀')),
+            (r"(char-upcase #\a)", Char('A')),
+            (r"(char-upcase #\λ)", Char('Λ')),
+            (r"(char-upcase #\1)", Char('1')),
+            (r"(char-upcase #\ß)", Char('ß')),
+            (r#"(string-ref "héllo" 1)"#, Char('é')),
+            (r#"(string-ref "a😀b" 2)"#, Char('b')),
+            (r"(= 1 (get {#\a 1} #\x61))", Bool(true)),
+        ];
+        for (src, expected) in input {
+            assert_eq!(eval(src).unwrap(), expected, "`{}`", src);
+        }
+
+        let input = [
+            r"(char->integer 65)", "(integer->char 0xD800)", "(integer->char 0x110000)", "(integer->char -1)",
+            r"(integer->char #\a)", r#"(char-upcase "a")"#, r#"(string-ref "ab" 2)"#, r"(string-ref #\a 0)",
+        ];
+        for src in input {
+            assert!(eval(src).is_err(), "`{}` should fail", src);
+        }
+    }
+}
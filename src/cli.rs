@@ -0,0 +1,127 @@
+use std::fs;
+use std::io::{self, Read};
+use std::sync::Arc;
+
+use crate::ast::{parse_program, read_program, Val};
+use crate::span::Source;
+use crate::{repl, Error, System};
+
+pub const USAGE: &str = "\
+usage: alisp                      start the REPL
+       alisp <file> [args...]     run a script
+       alisp -e <expr> [args...]  evaluate an expression and print the result
+       alisp - [args...]          run a script read from stdin";
+
+#[derive(PartialEq, Debug)]
+pub enum Command {
+    Repl,
+    Script { path: String, args: Vec<String> },
+    Eval { src: String, args: Vec<String> },
+    Stdin { args: Vec<String> },
+}
+
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    let Some((first, rest)) = args.split_first() else {
+        return Ok(Command::Repl);
+    };
+    match first.as_str() {
+        "-e" => match rest.split_first() {
+            Some((src, args)) => Ok(Command::Eval { src: src.clone(), args: args.to_vec() }),
+            None => Err(String::from("`-e` expects an expression")),
+        },
+        "-" => Ok(Command::Stdin { args: rest.to_vec() }),
+        flag if flag.starts_with('-') => Err(format!("unknown option `{}`", flag)),
+        path => Ok(Command::Script { path: String::from(path), args: rest.to_vec() }),
+    }
+}
+
+fn run_source(sys: &mut System, source: &Arc<Source>) -> crate::Result<Val> {
+    sys.eval_program(&read_program(source)?)
+}
+
+// the script's arguments are available to it as the list `argv`
+fn set_argv(sys: &mut System, args: &[String]) -> crate::Result<()> {
+    let argv = args.iter().cloned().map(Val::Str).collect();
+    sys.set(String::from("argv"), Val::List(argv))
+}
+
+pub fn run(sys: &mut System, command: Command) -> crate::Result<()> {
+    match command {
+        Command::Repl => {
+            set_argv(sys, &[])?;
+            repl::run(sys)
+        }
+        Command::Script { path, args } => {
+            let text = fs::read_to_string(&path)
+                .map_err(|err| Error::io(format!("cannot read `{}`", path), &err))?;
+            set_argv(sys, &args)?;
+            run_source(sys, &Source::new(&path, &text))?;
+            Ok(())
+        }
+        Command::Eval { src, args } => {
+            set_argv(sys, &args)?;
+            let val = sys.eval_program(&parse_program(&src)?)?;
+            println!("{}", val);
+            Ok(())
+        }
+        Command::Stdin { args } => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text).map_err(|err| Error::io("cannot read `<stdin>`", &err))?;
+            set_argv(sys, &args)?;
+            run_source(sys, &Source::new("<stdin>", &text))?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| String::from(*s)).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let input = [
+            args(&[]),
+            args(&["script.lisp", "a", "-e"]),
+            args(&["-e", "(+ 1 2)", "x"]),
+            args(&["-", "x"]),
+        ];
+        let result = input
+            .iter()
+            .map(|args| parse_args(args).unwrap())
+            .collect::<Vec<_>>();
+        let expected = vec![
+            Command::Repl,
+            Command::Script { path: String::from("script.lisp"), args: args(&["a", "-e"]) },
+            Command::Eval { src: String::from("(+ 1 2)"), args: args(&["x"]) },
+            Command::Stdin { args: args(&["x"]) },
+        ];
+        assert_eq!(result, expected);
+
+        assert!(parse_args(&args(&["-e"])).is_err());
+        assert!(parse_args(&args(&["--nope"])).is_err());
+    }
+
+    #[test]
+    fn test_run_script() {
+        let path = std::env::temp_dir().join(format!("alisp_script_test_{}.lisp", std::process::id()));
+        fs::write(&path, "#!/usr/bin/env alisp\n(+ 1 2)\n\n(+ 1 (undefined argv))\n").unwrap();
+
+        let mut sys = System::new();
+        let command = Command::Script { path: path.to_string_lossy().into_owned(), args: args(&["a"]) };
+        let err = run(&mut sys, command).unwrap_err();
+        assert_eq!(err.to_string(), "name `undefined` is undefined");
+        assert_eq!(sys.get(String::from("argv")).unwrap(), Val::List(vec![Val::Str(String::from("a"))].into()));
+        fs::remove_file(&path).unwrap();
+
+        let command = Command::Script { path: path.to_string_lossy().into_owned(), args: args(&[]) };
+        let err = run(&mut sys, command).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Io { .. }));
+        assert!(err.to_string().starts_with(&format!("cannot read `{}`: ", path.display())));
+    }
+}
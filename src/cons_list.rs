@@ -0,0 +1,150 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::Val;
+
+// an immutable singly linked list. `cons` shares the list it's given as its tail instead of
+// copying it, so `cons`, `car` and `cdr` are all O(1)
+#[derive(Clone, Default)]
+pub struct ConsList(Option<Rc<Node>>);
+
+struct Node {
+    head: Val,
+    tail: ConsList,
+}
+
+impl ConsList {
+    pub fn new() -> Self {
+        ConsList(None)
+    }
+
+    pub fn cons(&self, head: Val) -> Self {
+        ConsList(Some(Rc::new(Node { head, tail: self.clone() })))
+    }
+
+    pub fn car(&self) -> Option<&Val> {
+        self.0.as_ref().map(|node| &node.head)
+    }
+
+    pub fn cdr(&self) -> Option<&ConsList> {
+        self.0.as_ref().map(|node| &node.tail)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+
+    // O(n)
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter(self)
+    }
+
+    pub fn to_vec(&self) -> Vec<Val> {
+        self.iter().cloned().collect()
+    }
+}
+
+pub struct Iter<'a>(&'a ConsList);
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Val;
+
+    fn next(&mut self) -> Option<&'a Val> {
+        let node = self.0.0.as_ref()?;
+        self.0 = &node.tail;
+        Some(&node.head)
+    }
+}
+
+impl<'a> IntoIterator for &'a ConsList {
+    type Item = &'a Val;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl FromIterator<Val> for ConsList {
+    fn from_iter<I: IntoIterator<Item = Val>>(iter: I) -> Self {
+        Self::from(iter.into_iter().collect::<Vec<_>>())
+    }
+}
+
+impl From<Vec<Val>> for ConsList {
+    fn from(items: Vec<Val>) -> Self {
+        items.into_iter().rev().fold(ConsList::new(), |list, item| list.cons(item))
+    }
+}
+
+// dropping a long list node by node would recurse once per node, so unlink the nodes nothing else
+// shares in a loop instead
+impl Drop for ConsList {
+    fn drop(&mut self) {
+        let mut next = self.0.take();
+        while let Some(node) = next {
+            match Rc::try_unwrap(node) {
+                Ok(mut node) => next = node.tail.0.take(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl PartialEq for ConsList {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+impl Eq for ConsList {}
+impl PartialOrd for ConsList {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for ConsList {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl fmt::Debug for ConsList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Val::Int;
+
+    #[test]
+    fn test_sharing() {
+        let tail = ConsList::from(vec![Int(2), Int(3)]);
+        let list = tail.cons(Int(1));
+        assert_eq!(list.to_vec(), vec![Int(1), Int(2), Int(3)]);
+        assert_eq!(list.car(), Some(&Int(1)));
+        assert!(Rc::ptr_eq(list.cdr().unwrap().0.as_ref().unwrap(), tail.0.as_ref().unwrap()));
+        assert_eq!(tail.len(), 2);
+
+        let empty = ConsList::new();
+        assert!(empty.is_empty());
+        assert_eq!((empty.car(), empty.cdr()), (None, None));
+
+        // dropping a shared tail leaves the lists built on it alone
+        drop(tail);
+        assert_eq!(list.len(), 3);
+    }
+
+    #[test]
+    fn test_drop_long_list() {
+        let list = (0..1_000_000).map(Int).collect::<ConsList>();
+        assert_eq!(list.car(), Some(&Int(0)));
+        drop(list);
+    }
+}
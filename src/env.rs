@@ -0,0 +1,110 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::Val;
+
+// one frame of bindings; names that aren't bound here are looked up in the parent
+#[derive(Default)]
+pub struct Env {
+    vars: RefCell<HashMap<String, Val>>,
+    parent: Option<Rc<Env>>,
+}
+impl Env {
+    pub fn new(vars: HashMap<String, Val>, parent: &Rc<Env>) -> Rc<Self> {
+        Rc::new(Self { vars: RefCell::new(vars), parent: Some(parent.clone()) })
+    }
+
+    pub fn get(&self, sym: &str) -> Option<Val> {
+        match self.vars.borrow().get(sym) {
+            Some(val) => Some(val.clone()),
+            None => self.parent.as_ref()?.get(sym),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn contains(&self, sym: &str) -> bool {
+        self.vars.borrow().contains_key(sym)
+    }
+
+    // like `define`, but fails instead of replacing an existing binding in this frame
+    pub fn try_define(&self, sym: String, val: Val) -> Result<(), Val> {
+        match self.vars.borrow_mut().try_insert(sym, val) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.value),
+        }
+    }
+
+    // binds `sym` in this frame, shadowing any binding in the parents
+    pub fn define(&self, sym: String, val: Val) {
+        self.vars.borrow_mut().insert(sym, val);
+    }
+
+    // changes the innermost existing binding of `sym`, returning whether there was one
+    pub fn assign(&self, sym: &str, val: Val) -> bool {
+        if let Some(slot) = self.vars.borrow_mut().get_mut(sym) {
+            *slot = val;
+            return true;
+        }
+        match &self.parent {
+            Some(parent) => parent.assign(sym, val),
+            None => false,
+        }
+    }
+
+    // drops the bindings of a frame that only its own closures refer to, once the code that was
+    // using it is done with it, e.g. when a call returns. A closure defined in a frame keeps the
+    // frame alive and the frame keeps the closure, so such a frame would otherwise never be freed.
+    pub fn release(self: &Rc<Self>) {
+        let internal = self
+            .vars
+            .borrow()
+            .values()
+            .filter(|val| matches!(val, Val::Func(func) if Rc::strong_count(func) == 1 && func.captures(self)))
+            .count();
+        if internal > 0 && Rc::strong_count(self) == 1 + internal {
+            // dropping the closures drops their references to this frame, so not while it's borrowed
+            let vars = self.vars.take();
+            drop(vars);
+        }
+    }
+
+    // the bindings of this frame only, sorted by name
+    pub fn bindings(&self) -> Vec<(String, Val)> {
+        let mut bindings = self
+            .vars
+            .borrow()
+            .iter()
+            .map(|(sym, val)| (sym.clone(), val.clone()))
+            .collect::<Vec<_>>();
+        bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+        bindings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Val::Int;
+
+    #[test]
+    fn test_env_chain() {
+        let globals = Rc::new(Env::default());
+        globals.define(String::from("x"), Int(1));
+        globals.define(String::from("y"), Int(2));
+
+        let local = Env::new(HashMap::from([(String::from("x"), Int(10))]), &globals);
+        assert_eq!(local.get("x"), Some(Int(10)));
+        assert_eq!(local.get("y"), Some(Int(2)));
+        assert_eq!(local.get("z"), None);
+        assert!(local.contains("x") && !local.contains("y"));
+
+        // assignment changes the innermost binding
+        assert!(local.assign("x", Int(11)));
+        assert!(local.assign("y", Int(12)));
+        assert!(!local.assign("z", Int(13)));
+        assert_eq!(globals.get("x"), Some(Int(1)));
+        assert_eq!(globals.get("y"), Some(Int(12)));
+        assert_eq!(local.bindings(), vec![(String::from("x"), Int(11))]);
+    }
+}
@@ -0,0 +1,183 @@
+use std::fmt;
+use std::io;
+
+use crate::ast::Val;
+use crate::span::{Diagnostic, Span};
+use crate::Arity;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// boxed so that a `Result` is no bigger than its value, which keeps the frames of the evaluator's
+// recursion small
+#[derive(Clone, Debug)]
+pub struct Error(Box<ErrorKind>);
+
+// everything that can go wrong reading or running code. Errors raised away from any code, e.g. by a
+// builtin, start with an empty span and get the span of the expression they came out of.
+#[derive(Clone, Debug)]
+pub enum ErrorKind {
+    // the source isn't well-formed. It's `incomplete` if it ends in the middle of a form, string or
+    // comment, so more input could still make it well-formed.
+    ParseError { message: String, incomplete: bool, span: Span },
+    UndefinedName { name: String, span: Span },
+    // globals and macros can only be defined once
+    Reassignment { name: String, span: Span },
+    // `expected` describes what `func` takes, e.g. "a list"
+    TypeError { func: String, expected: String, got: Val, span: Span },
+    ArityError { func: String, expected: Arity, got: usize, span: Span },
+    DivideByZero { func: String, span: Span },
+    IndexOutOfRange { func: String, index: i64, span: Span },
+    NotCallable { got: Val, span: Span },
+    // arguments of the right types that a builtin still can't use
+    InvalidArgument { func: String, message: String, span: Span },
+    // a special form or macro used with the wrong shape
+    SyntaxError { message: String, span: Span },
+    // a value passed to `raise`
+    UserRaised { val: Val, span: Span },
+    // evaluation nested more than `limit` expressions deep, e.g. through runaway recursion
+    RecursionLimit { limit: usize, span: Span },
+    // reading a script or the terminal failed; `context` says what was being read
+    Io { context: String, message: String, span: Span },
+}
+
+impl Error {
+    pub fn parse(message: impl Into<String>, span: &Span) -> Self {
+        Error::from(ErrorKind::ParseError { message: message.into(), incomplete: false, span: span.clone() })
+    }
+
+    pub fn incomplete(message: impl Into<String>, span: &Span) -> Self {
+        Error::from(ErrorKind::ParseError { message: message.into(), incomplete: true, span: span.clone() })
+    }
+
+    pub fn undefined(name: &str) -> Self {
+        Error::from(ErrorKind::UndefinedName { name: name.to_string(), span: Span::default() })
+    }
+
+    pub fn reassignment(name: &str) -> Self {
+        Error::from(ErrorKind::Reassignment { name: name.to_string(), span: Span::default() })
+    }
+
+    pub fn type_error(func: &str, expected: &str, got: &Val) -> Self {
+        let (func, expected) = (func.to_string(), expected.to_string());
+        Error::from(ErrorKind::TypeError { func, expected, got: got.clone(), span: Span::default() })
+    }
+
+    pub fn arity(func: &str, expected: Arity, got: usize) -> Self {
+        Error::from(ErrorKind::ArityError { func: func.to_string(), expected, got, span: Span::default() })
+    }
+
+    pub fn divide_by_zero(func: &str) -> Self {
+        Error::from(ErrorKind::DivideByZero { func: func.to_string(), span: Span::default() })
+    }
+
+    pub fn index_out_of_range(func: &str, index: i64) -> Self {
+        Error::from(ErrorKind::IndexOutOfRange { func: func.to_string(), index, span: Span::default() })
+    }
+
+    pub fn not_callable(got: &Val) -> Self {
+        Error::from(ErrorKind::NotCallable { got: got.clone(), span: Span::default() })
+    }
+
+    pub fn invalid_argument(func: &str, message: impl Into<String>) -> Self {
+        let (func, message) = (func.to_string(), message.into());
+        Error::from(ErrorKind::InvalidArgument { func, message, span: Span::default() })
+    }
+
+    pub fn syntax(message: impl Into<String>) -> Self {
+        Error::from(ErrorKind::SyntaxError { message: message.into(), span: Span::default() })
+    }
+
+    pub fn raised(val: Val) -> Self {
+        Error::from(ErrorKind::UserRaised { val, span: Span::default() })
+    }
+
+    pub fn recursion_limit(limit: usize) -> Self {
+        Error::from(ErrorKind::RecursionLimit { limit, span: Span::default() })
+    }
+
+    pub fn io(context: impl Into<String>, err: &io::Error) -> Self {
+        Error::from(ErrorKind::Io { context: context.into(), message: err.to_string(), span: Span::default() })
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.0
+    }
+
+    pub fn span(&self) -> &Span {
+        match self.kind() {
+            ErrorKind::ParseError { span, .. }
+            | ErrorKind::UndefinedName { span, .. }
+            | ErrorKind::Reassignment { span, .. }
+            | ErrorKind::TypeError { span, .. }
+            | ErrorKind::ArityError { span, .. }
+            | ErrorKind::DivideByZero { span, .. }
+            | ErrorKind::IndexOutOfRange { span, .. }
+            | ErrorKind::NotCallable { span, .. }
+            | ErrorKind::InvalidArgument { span, .. }
+            | ErrorKind::SyntaxError { span, .. }
+            | ErrorKind::UserRaised { span, .. }
+            | ErrorKind::RecursionLimit { span, .. }
+            | ErrorKind::Io { span, .. } => span,
+        }
+    }
+
+    // gives the error the span of `span` if it doesn't have a location yet
+    pub fn locate(mut self, span: &Span) -> Self {
+        if self.span().source.is_none() {
+            match self.0.as_mut() {
+                ErrorKind::ParseError { span: own, .. }
+                | ErrorKind::UndefinedName { span: own, .. }
+                | ErrorKind::Reassignment { span: own, .. }
+                | ErrorKind::TypeError { span: own, .. }
+                | ErrorKind::ArityError { span: own, .. }
+                | ErrorKind::DivideByZero { span: own, .. }
+                | ErrorKind::IndexOutOfRange { span: own, .. }
+                | ErrorKind::NotCallable { span: own, .. }
+                | ErrorKind::InvalidArgument { span: own, .. }
+                | ErrorKind::SyntaxError { span: own, .. }
+                | ErrorKind::UserRaised { span: own, .. }
+                | ErrorKind::RecursionLimit { span: own, .. }
+                | ErrorKind::Io { span: own, .. } => *own = span.clone(),
+            }
+        }
+        self
+    }
+
+    // the error as it is shown to the user, located in its source when it has a span
+    pub fn render(&self) -> String {
+        Diagnostic::new(self.to_string(), self.span()).render()
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error(Box::new(kind))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind() {
+            ErrorKind::ParseError { message, .. } | ErrorKind::SyntaxError { message, .. } => write!(f, "{}", message),
+            ErrorKind::UndefinedName { name, .. } => write!(f, "name `{}` is undefined", name),
+            ErrorKind::Reassignment { name, .. } => write!(f, "cannot reassign `{}`", name),
+            ErrorKind::TypeError { func, expected, got, .. } => write!(f, "`{}` expects {}, got `{}`", func, expected, got),
+            ErrorKind::ArityError { func, expected, got, .. } => {
+                write!(f, "function `{}` expects {}, got {}", func, expected, got)
+            }
+            ErrorKind::DivideByZero { func, .. } => write!(f, "division by zero in `{}`", func),
+            ErrorKind::IndexOutOfRange { func, index, .. } => write!(f, "index {} is out of range in `{}`", index, func),
+            ErrorKind::NotCallable { got, .. } => write!(f, "cannot call non-function `{}`", got),
+            ErrorKind::InvalidArgument { func, message, .. } => write!(f, "`{}` {}", func, message),
+            // a raised string is the message itself
+            ErrorKind::UserRaised { val: Val::Str(message), .. } => write!(f, "{}", message),
+            ErrorKind::UserRaised { val, .. } => write!(f, "raised `{}`", val),
+            ErrorKind::RecursionLimit { limit, .. } => {
+                write!(f, "recursion limit exceeded: expressions nested more than {} deep", limit)
+            }
+            ErrorKind::Io { context, message, .. } => write!(f, "{}: {}", context, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,520 @@
+use std::iter::Peekable;
+use std::str::CharIndices;
+use std::sync::Arc;
+
+use crate::ast::{regexes, Val};
+use crate::bigint::BigInt;
+use crate::number::{parse_number, Rational};
+use crate::error::{Error, Result};
+use crate::span::{Source, Span};
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Token {
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    // `#{`, which starts a set
+    HashBrace,
+    Quote,
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing,
+    // `#;`, which comments out the next form
+    DatumComment,
+    Int(i64),
+    Big(BigInt),
+    Ratio(Rational),
+    Float(f64),
+    Char(char),
+    Str(String),
+    Bool(bool),
+    Nil,
+    // without the leading `:`
+    Keyword(String),
+    Sym(String),
+}
+
+pub fn tokenize(source: &Arc<Source>) -> Result<Vec<(Token, Span)>> {
+    let mut lexer = Lexer {
+        source,
+        chars: source.text.char_indices().peekable(),
+        line: 1,
+        column: 1,
+    };
+
+    // a `#!` line at the very start lets scripts be executed directly
+    if source.text.starts_with("#!") {
+        lexer.skip_line();
+    }
+
+    let mut tokens = vec![];
+    while let Some(c) = lexer.peek() {
+        let start = lexer.span();
+        let token = match c {
+            '(' => {
+                lexer.next();
+                Token::LParen
+            }
+            ')' => {
+                lexer.next();
+                Token::RParen
+            }
+            '{' => {
+                lexer.next();
+                Token::LBrace
+            }
+            '}' => {
+                lexer.next();
+                Token::RBrace
+            }
+            '[' => {
+                lexer.next();
+                Token::LBracket
+            }
+            ']' => {
+                lexer.next();
+                Token::RBracket
+            }
+            '\'' => {
+                lexer.next();
+                Token::Quote
+            }
+            '`' => {
+                lexer.next();
+                Token::Quasiquote
+            }
+            ',' => {
+                lexer.next();
+                if lexer.peek() == Some('@') {
+                    lexer.next();
+                    Token::UnquoteSplicing
+                } else {
+                    Token::Unquote
+                }
+            }
+            '"' => lexer.lex_str(&start)?,
+            ';' => {
+                lexer.skip_line();
+                continue;
+            }
+            '#' => match lexer.peek_second() {
+                Some('|') => {
+                    lexer.skip_block_comment(&start)?;
+                    continue;
+                }
+                Some(';') => {
+                    lexer.next();
+                    lexer.next();
+                    Token::DatumComment
+                }
+                Some('{') => {
+                    lexer.next();
+                    lexer.next();
+                    Token::HashBrace
+                }
+                Some('r') => lexer.lex_raw_str(&start)?,
+                Some('\\') => lexer.lex_char(&start)?,
+                _ => lexer.lex_word(&start)?,
+            },
+            c if c.is_whitespace() => {
+                lexer.next();
+                continue;
+            }
+            _ => lexer.lex_word(&start)?,
+        };
+        tokens.push((token, start.to(&lexer.span())));
+    }
+
+    Ok(tokens)
+}
+
+// the characters written by name, as in `#\space`
+pub const CHAR_NAMES: [(&str, char); 10] = [
+    ("space", ' '),
+    ("newline", '\n'),
+    ("tab", '\t'),
+    ("return", '\r'),
+    ("nul", '\0'),
+    ("null", '\0'),
+    ("alarm", '\x07'),
+    ("backspace", '\x08'),
+    ("delete", '\x7f'),
+    ("escape", '\x1b'),
+];
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '(' | ')' | '{' | '}' | '[' | ']' | '\'' | '`' | ',' | '"' | ';')
+}
+
+struct Lexer<'a> {
+    source: &'a Arc<Source>,
+    chars: Peekable<CharIndices<'a>>,
+    line: usize,
+    column: usize,
+}
+impl<'a> Lexer<'a> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, c)| *c)
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        let mut chars = self.chars.clone();
+        chars.next();
+        chars.next().map(|(_, c)| c)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    // an empty span at the current position
+    fn span(&mut self) -> Span {
+        let offset = self.chars.peek().map_or(self.source.text.len(), |(i, _)| *i);
+        Span {
+            source: Some(self.source.clone()),
+            line: self.line,
+            column: self.column,
+            start: offset,
+            end: offset,
+        }
+    }
+
+    fn skip_line(&mut self) {
+        while self.peek().is_some_and(|c| c != '\n') {
+            self.next();
+        }
+    }
+
+    // `#| ... |#`, which may be nested
+    fn skip_block_comment(&mut self, start: &Span) -> Result<()> {
+        let mut depth = 0;
+        loop {
+            match (self.next(), self.peek()) {
+                (Some('#'), Some('|')) => {
+                    self.next();
+                    depth += 1;
+                }
+                (Some('|'), Some('#')) => {
+                    self.next();
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                (Some(_), _) => {}
+                (None, _) => {
+                    return Err(Error::incomplete("unterminated block comment", &start.to(&self.span())))
+                }
+            }
+        }
+    }
+
+    fn lex_str(&mut self, start: &Span) -> Result<Token> {
+        // opening quote
+        self.next();
+
+        let mut text = String::new();
+        loop {
+            let escape_start = self.span();
+            match self.next() {
+                Some('"') => return Ok(Token::Str(text)),
+                Some('\\') => text.push(self.lex_escape(&escape_start)?),
+                Some(c) => text.push(c),
+                None => {
+                    return Err(Error::incomplete("unterminated string", &start.to(&self.span())))
+                }
+            }
+        }
+    }
+
+    // called after the backslash has been consumed
+    fn lex_escape(&mut self, start: &Span) -> Result<char> {
+        let escaped = match self.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('"') => '"',
+            Some('u') if self.peek() == Some('{') => {
+                self.next();
+                let mut digits = String::new();
+                while let Some(c) = self.peek().filter(char::is_ascii_hexdigit) {
+                    digits.push(c);
+                    self.next();
+                }
+                let scalar = u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32);
+                match (self.next(), scalar) {
+                    (Some('}'), Some(c)) => c,
+                    _ => {
+                        let span = start.to(&self.span());
+                        return Err(Error::parse("invalid unicode escape", &span));
+                    }
+                }
+            }
+            Some(c) => {
+                let span = start.to(&self.span());
+                return Err(Error::parse(format!("unknown escape `\\{}`", c), &span));
+            }
+            None => return Err(Error::incomplete("unterminated string", &start.to(&self.span()))),
+        };
+        Ok(escaped)
+    }
+
+    // `#r"..."` has no escapes, and `#r#"..."#` (with any number of `#`s) may contain quotes
+    fn lex_raw_str(&mut self, start: &Span) -> Result<Token> {
+        // `#r`
+        self.next();
+        self.next();
+
+        let mut hashes = 0;
+        while self.peek() == Some('#') {
+            self.next();
+            hashes += 1;
+        }
+        if self.next() != Some('"') {
+            return Err(Error::parse("malformed raw string", &start.to(&self.span())));
+        }
+
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some(c) => text.push(c),
+                None => {
+                    return Err(Error::incomplete("unterminated string", &start.to(&self.span())))
+                }
+            }
+            if text.ends_with(&terminator) {
+                text.truncate(text.len() - terminator.len());
+                return Ok(Token::Str(text));
+            }
+        }
+    }
+
+    // `#\a`, `#\space` or `#\x41`. The first character is taken as it is, so `#\(` and `#\ ` work.
+    fn lex_char(&mut self, start: &Span) -> Result<Token> {
+        // `#\`
+        self.next();
+        self.next();
+
+        let Some(first) = self.next() else {
+            return Err(Error::incomplete("unterminated character", &start.to(&self.span())));
+        };
+        let mut name = String::from(first);
+        while let Some(c) = self.peek().filter(|c| !is_delimiter(*c)) {
+            name.push(c);
+            self.next();
+        }
+
+        let mut chars = name.chars();
+        let c = match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            // `#\x` on its own is the letter
+            (Some('x'), Some(_)) => u32::from_str_radix(&name[1..], 16).ok().and_then(char::from_u32),
+            _ => CHAR_NAMES.iter().find(|(known, _)| *known == name).map(|(_, c)| *c),
+        };
+        match c {
+            Some(c) => Ok(Token::Char(c)),
+            None => Err(Error::parse(format!("unknown character `#\\{}`", name), &start.to(&self.span()))),
+        }
+    }
+
+    fn lex_word(&mut self, start: &Span) -> Result<Token> {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            // a `#` can be part of a word, but not the start of a block comment
+            if is_delimiter(c) || (c == '#' && self.peek_second() == Some('|')) {
+                break;
+            }
+            word.push(c);
+            self.next();
+        }
+
+        let span = start.to(&self.span());
+        if let Some(number) = parse_number(&word) {
+            return match number.map_err(|err| err.locate(&span))? {
+                Val::Int(i) => Ok(Token::Int(i)),
+                Val::Big(big) => Ok(Token::Big(big)),
+                Val::Ratio(ratio) => Ok(Token::Ratio(ratio)),
+                Val::Float(f) => Ok(Token::Float(f)),
+                _ => unreachable!(),
+            };
+        }
+        // `true`, `false` and `nil` look like symbols, but aren't
+        match word.as_str() {
+            "#t" | "#true" | "true" => Ok(Token::Bool(true)),
+            "#f" | "#false" | "false" => Ok(Token::Bool(false)),
+            "nil" => Ok(Token::Nil),
+            _ if regexes::KEYWORD.is_match(&word) => Ok(Token::Keyword(word[1..].to_string())),
+            _ if regexes::SYM.is_match(&word) => Ok(Token::Sym(word)),
+            _ => Err(Error::parse(format!("malformed value: `{}`", word), &span)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(src: &str) -> Result<Vec<Token>> {
+        let tokens = tokenize(&Source::new("test", src))?;
+        Ok(tokens.into_iter().map(|(token, _)| token).collect())
+    }
+
+    #[test]
+    fn test_tokenize_nested() {
+        use Token::*;
+
+        let result = tokens(r#"(f (a 1) '(b) "x y" 2.5)"#).unwrap();
+        let expected = vec![
+            LParen,
+            Sym(String::from("f")),
+            LParen,
+            Sym(String::from("a")),
+            Int(1),
+            RParen,
+            Quote,
+            LParen,
+            Sym(String::from("b")),
+            RParen,
+            Str(String::from("x y")),
+            Float(2.5),
+            RParen,
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_tokenize_quasiquote() {
+        use Token::*;
+
+        let result = tokens("`(a ,b ,@c)").unwrap();
+        let expected = vec![
+            Quasiquote, LParen, Sym(String::from("a")), Unquote, Sym(String::from("b")), UnquoteSplicing,
+            Sym(String::from("c")), RParen,
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_tokenize_numbers() {
+        use Token::*;
+
+        let result = tokens("(- -5 +.5 -x 1_000 0x1F)").unwrap();
+        let expected = vec![
+            LParen, Sym(String::from("-")), Int(-5), Float(0.5), Sym(String::from("-x")), Int(1000), Int(31), RParen,
+        ];
+        assert_eq!(result, expected);
+        assert!(tokens("(f 007)").is_err());
+    }
+
+    #[test]
+    fn test_tokenize_chars() {
+        use Token::*;
+
+        let result = tokens(r"(f #\a #\space #\newline #\x41 #\x #\( #\) #\λ #\x3bb)").unwrap();
+        let expected = vec![
+            LParen, Sym(String::from("f")), Char('a'), Char(' '), Char('\n'), Char('A'), Char('x'), Char('('), Char(')'),
+            Char('λ'), Char('λ'), RParen,
+        ];
+        assert_eq!(result, expected);
+        assert_eq!(tokens(r"#\ ").unwrap(), vec![Char(' ')]);
+
+        for src in [r"#\", r"#\spaces", r"#\ab", r"#\xd800", r"#\x110000", r"#\xg"] {
+            assert!(tokens(src).is_err(), "`{}` should not tokenize", src);
+        }
+    }
+
+    #[test]
+    fn test_tokenize_braces() {
+        use Token::*;
+
+        let result = tokens("{:a #{1}}").unwrap();
+        let expected = vec![LBrace, Keyword(String::from("a")), HashBrace, Int(1), RBrace, RBrace];
+        assert_eq!(result, expected);
+
+        let result = tokens("[a[1]]").unwrap();
+        let expected = vec![LBracket, Sym(String::from("a")), LBracket, Int(1), RBracket, RBracket];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_tokenize_spans() {
+        let tokens = tokenize(&Source::new("test", "(f\n  \"é\" 12)")).unwrap();
+        let spans = tokens
+            .iter()
+            .map(|(_, span)| (span.line, span.column, span.start, span.end))
+            .collect::<Vec<_>>();
+        let expected = vec![(1, 1, 0, 1), (1, 2, 1, 2), (2, 3, 5, 9), (2, 7, 10, 12), (2, 9, 12, 13)];
+        assert_eq!(spans, expected);
+    }
+
+    #[test]
+    fn test_tokenize_comments() {
+        use Token::*;
+
+        let src = "; leading\n(f ; trailing (\n 1 #| block ) #| nested |# \" |# 2)#;(g)";
+        let result = tokens(src).unwrap();
+        let expected = vec![
+            LParen, Sym(String::from("f")), Int(1), Int(2), RParen, DatumComment, LParen, Sym(String::from("g")), RParen,
+        ];
+        assert_eq!(result, expected);
+
+        assert!(tokens("(f #| #| |# 1)").is_err());
+
+        // comments end the word before them
+        let result = tokens("x; note\n(f a#|c|#1 #\\a;c\n)").unwrap();
+        let expected = vec![
+            Sym(String::from("x")), LParen, Sym(String::from("f")), Sym(String::from("a")), Int(1), Char('a'), RParen,
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_tokenize_shebang() {
+        use Token::*;
+
+        let result = tokens("#!/usr/bin/env alisp\n(f)").unwrap();
+        assert_eq!(result, vec![LParen, Sym(String::from("f")), RParen]);
+        assert!(tokens("(f)\n#!/usr/bin/env alisp").is_err());
+    }
+
+    #[test]
+    fn test_tokenize_strings() {
+        use Token::Str;
+
+        let src = r###""a\"b" "\n\t\\\u{1F600}" "two
+lines" #r"C:\dir" #r##"say "#hi"#"## "é""###;
+        let result = tokens(src).unwrap();
+        let expected = vec![
+            Str(String::from("a\"b")),
+            Str(String::from("\n\t\\😀")),
+            Str(String::from("two\nlines")),
+            Str(String::from("C:\\dir")),
+            Str(String::from("say \"#hi\"#")),
+            Str(String::from("é")),
+        ];
+        assert_eq!(result, expected);
+
+        for src in [r#""\q""#, r#""\u{110000}""#, r#""\u{41""#, r#""\u41""#, r##"#r"abc"##, "#rx"] {
+            assert!(tokens(src).is_err(), "`{}` should not lex", src);
+        }
+    }
+
+    #[test]
+    fn test_tokenize_unterminated_string() {
+        assert!(tokens(r#"(f "abc)"#).is_err());
+        assert!(tokens(r#"(f "abc\")"#).is_err());
+    }
+}
@@ -0,0 +1,779 @@
+// the interpreter as a library: read code with `read_program`, run it with `System::eval_program`,
+// and match on the `ErrorKind` of what fails. `main.rs` is the command line front end.
+#![feature(map_try_insert)]
+// functions are ordered by address, so the `RefCell`s inside them can't change where a key sorts
+#![allow(clippy::mutable_key_type)]
+
+#[macro_use]
+extern crate lazy_static;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+mod ast;
+mod bigint;
+mod builtins;
+pub mod cli;
+mod cons_list;
+mod env;
+mod error;
+mod lexer;
+mod line_editor;
+mod macros;
+mod number;
+mod printer;
+mod repl;
+mod span;
+mod special_forms;
+mod symbol;
+mod syntax_rules;
+pub use ast::{parse_program, read_expr, read_program, try_parse_atom, try_parse_expr, Expr, ExprKind, Val};
+use env::Env;
+pub use error::{Error, ErrorKind, Result};
+use macros::{Macro, Macros};
+pub use span::{Source, Span};
+
+pub type BuiltinFn = fn(&mut System, Vec<Val>) -> Result<Val>;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Arity {
+    Exact(usize),
+    AtLeast(usize),
+    // inclusive
+    Range(usize, usize),
+}
+impl Arity {
+    fn accepts(&self, n: usize) -> bool {
+        match *self {
+            Arity::Exact(m) => n == m,
+            Arity::AtLeast(m) => n >= m,
+            Arity::Range(min, max) => (min..=max).contains(&n),
+        }
+    }
+}
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Arity::Exact(1) => write!(f, "1 argument"),
+            Arity::Exact(n) => write!(f, "{} arguments", n),
+            Arity::AtLeast(1) => write!(f, "at least 1 argument"),
+            Arity::AtLeast(n) => write!(f, "at least {} arguments", n),
+            Arity::Range(0, 1) => write!(f, "at most 1 argument"),
+            Arity::Range(0, max) => write!(f, "at most {} arguments", max),
+            Arity::Range(min, max) => write!(f, "{} to {} arguments", min, max),
+        }
+    }
+}
+
+pub enum Function {
+    Builtin { name: String, arity: Arity, func: BuiltinFn },
+    // `rest` collects the arguments after `params`, if there is one
+    Lambda {
+        name: Option<String>,
+        params: Vec<String>,
+        rest: Option<String>,
+        body: Vec<Expr>,
+        env: Rc<Env>,
+    },
+}
+impl Function {
+    fn arity(&self) -> Arity {
+        match self {
+            Function::Builtin { arity, .. } => *arity,
+            Function::Lambda { params, rest: None, .. } => Arity::Exact(params.len()),
+            Function::Lambda { params, rest: Some(_), .. } => Arity::AtLeast(params.len()),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Function::Builtin { name, .. } => name,
+            Function::Lambda { name, .. } => name.as_deref().unwrap_or("lambda"),
+        }
+    }
+
+    // whether this is a closure over `env` itself
+    fn captures(&self, env: &Rc<Env>) -> bool {
+        matches!(self, Function::Lambda { env: captured, .. } if Rc::ptr_eq(captured, env))
+    }
+}
+// functions are only equal to themselves
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Function::Builtin { name, .. } => write!(f, "<builtin {}>", name),
+            Function::Lambda { .. } => write!(f, "<lambda {}>", self.name()),
+        }
+    }
+}
+// `#<` can't be read, so printed functions don't read back as something else
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:?}", self)
+    }
+}
+
+// what's left to do after evaluating an expression in tail position
+pub enum Step {
+    Done(Val),
+    // a call to a lambda whose arguments have been evaluated, made by the nearest trampoline
+    Call(Rc<Function>, Vec<Val>),
+}
+
+// how deeply expressions can be nested while they're evaluated, unless `System::set_max_depth`
+// says otherwise. Every level takes up to about 6 KB of Rust stack in a debug build, so this fits
+// in the 8 MB a main thread usually has, and recursion past it fails with an error instead of
+// overflowing the stack. Deeper limits need a thread with a bigger stack to evaluate on.
+pub const DEFAULT_MAX_DEPTH: usize = 1_000;
+
+pub struct System {
+    globals: Rc<Env>,
+    // the innermost scope of the code being evaluated
+    env: Rc<Env>,
+    macros: Macros,
+    // the number of expressions being evaluated, each inside the last
+    depth: usize,
+    max_depth: usize,
+    // scopes that a pending tail call's closure was created in, released once the call is over
+    held: Vec<Rc<Env>>,
+}
+impl Default for System {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl System {
+    pub fn new() -> Self {
+        let globals = Rc::new(Env::default());
+        let mut sys = Self {
+            env: globals.clone(),
+            globals,
+            macros: Macros::default(),
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            held: vec![],
+        };
+        builtins::install(&mut sys);
+        sys
+    }
+
+    // how deeply expressions can be nested before evaluating them fails, see `DEFAULT_MAX_DEPTH`
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    // errors that don't carry a location yet get the span of the innermost expression they came from
+    pub fn eval(&mut self, expr: &Expr) -> Result<Val> {
+        let held = self.held.len();
+        let result = match self.eval_step(expr) {
+            Ok(Step::Done(val)) => Ok(val),
+            Ok(Step::Call(func, args)) => self.run(func, args),
+            Err(err) => Err(err),
+        };
+        self.release_held(held);
+        result.map_err(|err| err.locate(&expr.span))
+    }
+
+    // evaluates `expr` in tail position: a call to a lambda is handed back to the caller instead
+    // of being made, so that loops written as tail recursion run in constant stack
+    pub fn eval_step(&mut self, expr: &Expr) -> Result<Step> {
+        if self.depth >= self.max_depth {
+            return Err(Error::recursion_limit(self.max_depth).locate(&expr.span));
+        }
+        self.depth += 1;
+        let result = self.eval_kind(&expr.kind, &expr.span);
+        self.depth -= 1;
+        result.map_err(|err| err.locate(&expr.span))
+    }
+
+    fn eval_kind(&mut self, kind: &ExprKind, span: &Span) -> Result<Step> {
+        use ExprKind::*;
+        match kind {
+            Atom(val) => Ok(Step::Done(val.clone())),
+            Sym(sym) => self.get(sym.clone()).map(Step::Done),
+            Func(name, args) => match special_forms::lookup(name) {
+                Some(form) => form(self, args),
+                None => match self.lookup_macro(name) {
+                    // the expansion is in the same position as the call, so it can make tail calls too
+                    Some(mac) => {
+                        let expanded = mac.expand(self, args, span)?;
+                        self.eval_step(&expanded)
+                    }
+                    None => self.call(name, args),
+                },
+            },
+            List(items) => match items.split_first() {
+                None => Err(Error::syntax("cannot evaluate empty form `()`")),
+                Some((head, args)) => {
+                    let func = self.eval(head)?;
+                    self.call_val(&func, args)
+                }
+            },
+            Map(entries) => {
+                let mut map = BTreeMap::new();
+                for (k, v) in entries {
+                    map.insert(self.eval(k)?, self.eval(v)?);
+                }
+                Ok(Step::Done(Val::Map(map)))
+            }
+            Set(items) => {
+                let set = items.iter().map(|item| self.eval(item)).collect::<Result<_>>()?;
+                Ok(Step::Done(Val::Set(set)))
+            }
+            Vector(items) => {
+                let vector = items.iter().map(|item| self.eval(item)).collect::<Result<_>>()?;
+                Ok(Step::Done(Val::Vector(vector)))
+            }
+        }
+    }
+
+    // evaluates the forms in order, returning the value of the last one
+    pub fn eval_program(&mut self, exprs: &[Expr]) -> Result<Val> {
+        let mut last = Val::Nil;
+        for expr in exprs {
+            last = self.eval(expr)?;
+        }
+        Ok(last)
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr]) -> Result<Step> {
+        let func = self.get(name.to_string())?;
+        self.call_val(&func, args)
+    }
+
+    fn call_val(&mut self, func: &Val, args: &[Expr]) -> Result<Step> {
+        let Val::Func(func) = func else {
+            return Err(Error::not_callable(func));
+        };
+        self.check_arity(func, args.len())?;
+
+        let args = args
+            .iter()
+            .map(|arg| self.eval(arg))
+            .collect::<Result<Vec<_>>>()?;
+        // builtins run straight away so their errors point at the call
+        match func.as_ref() {
+            Function::Builtin { func, .. } => func(self, args).map(Step::Done),
+            Function::Lambda { .. } => Ok(Step::Call(func.clone(), args)),
+        }
+    }
+
+    fn check_arity(&self, func: &Function, n: usize) -> Result<()> {
+        let arity = func.arity();
+        if arity.accepts(n) {
+            Ok(())
+        } else {
+            Err(Error::arity(func.name(), arity, n))
+        }
+    }
+
+    // calls `func` with arguments that have already been evaluated
+    pub fn apply(&mut self, func: &Val, args: Vec<Val>) -> Result<Val> {
+        let Val::Func(func) = func else {
+            return Err(Error::not_callable(func));
+        };
+        self.check_arity(func, args.len())?;
+        let held = self.held.len();
+        let result = self.run(func.clone(), args);
+        self.release_held(held);
+        result
+    }
+
+    // the trampoline: tail calls made by the body come back here and replace the current call
+    // rather than nesting inside it. The arity of `func` has already been checked.
+    fn run(&mut self, mut func: Rc<Function>, mut args: Vec<Val>) -> Result<Val> {
+        let caller = self.env.clone();
+        let result = loop {
+            let step = match func.as_ref() {
+                Function::Builtin { func, .. } => break func(self, args),
+                Function::Lambda { params, rest, body, env, .. } => {
+                    let rest_args = args.split_off(params.len());
+                    let mut vars = params.iter().cloned().zip(args).collect::<HashMap<_, _>>();
+                    if let Some(rest) = rest {
+                        vars.insert(rest.clone(), Val::List(rest_args.into()));
+                    }
+
+                    // the body sees the scope the lambda was created in, not the caller's
+                    self.env = Env::new(vars, env);
+                    special_forms::eval_body(self, body)
+                }
+            };
+            self.hold(&step);
+            self.env.release();
+            match step {
+                Ok(Step::Done(val)) => break Ok(val),
+                Ok(Step::Call(next, next_args)) => (func, args) = (next, next_args),
+                Err(err) => break Err(err),
+            }
+        };
+        self.env = caller;
+        result
+    }
+
+    // keeps the current scope from being released while a tail call to a closure created in it is
+    // pending
+    fn hold(&mut self, step: &Result<Step>) {
+        if let Ok(Step::Call(next, _)) = step {
+            let held = self.held.last().is_some_and(|last| Rc::ptr_eq(last, &self.env));
+            if next.captures(&self.env) && !held {
+                self.held.push(self.env.clone());
+            }
+        }
+    }
+
+    // releases the scopes held since there were `len`, innermost first since they keep their
+    // parents alive
+    fn release_held(&mut self, len: usize) {
+        while self.held.len() > len {
+            self.held.pop().unwrap().release();
+        }
+    }
+
+    // the scope a closure created right now would capture
+    pub fn capture(&self) -> Rc<Env> {
+        self.env.clone()
+    }
+
+    pub fn define_builtin(&mut self, name: &str, arity: Arity, func: BuiltinFn) -> Result<()> {
+        let builtin = Function::Builtin { name: name.to_string(), arity, func };
+        self.set(name.to_string(), Val::Func(Rc::new(builtin)))
+    }
+
+    #[cfg(test)]
+    pub fn define_func(&mut self, name: &str, params: Vec<String>, body: Expr) -> Result<()> {
+        let lambda = Function::Lambda {
+            name: Some(name.to_string()),
+            params,
+            rest: None,
+            body: vec![body],
+            env: self.globals.clone(),
+        };
+        self.set(name.to_string(), Val::Func(Rc::new(lambda)))
+    }
+
+    pub fn define_macro(&mut self, name: String, mac: Macro) -> Result<()> {
+        self.macros.define(name, mac)
+    }
+
+    // for `defmacro` and `define-syntax`, which replace a macro of the same name
+    pub fn redefine_macro(&mut self, name: String, mac: Macro) {
+        self.macros.redefine(name, mac)
+    }
+
+    pub fn lookup_macro(&self, name: &str) -> Option<Macro> {
+        self.macros.get(name)
+    }
+
+
+    // defines a global, which can't be redefined
+    pub fn set(&mut self, sym: String, val: Val) -> Result<()> {
+        match self.globals.try_define(sym.clone(), val) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error::reassignment(&sym)),
+        }
+    }
+
+    // the global bindings, sorted by name
+    pub fn globals(&self) -> Vec<(String, Val)> {
+        self.globals.bindings()
+    }
+
+    // runs `body` in a new scope with `vars` bound, e.g. for the bindings of a `let`
+    pub fn in_scope(
+        &mut self,
+        vars: HashMap<String, Val>,
+        body: impl FnOnce(&mut Self) -> Result<Step>,
+    ) -> Result<Step> {
+        let inner = Env::new(vars, &self.env);
+        let outer = std::mem::replace(&mut self.env, inner);
+        let result = body(self);
+        self.hold(&result);
+        std::mem::replace(&mut self.env, outer).release();
+        result
+    }
+
+    // binds `sym` in the innermost scope, or as a global at the top level. Unlike `set`, it
+    // replaces an existing binding, since code can define a name again.
+    pub fn define(&mut self, sym: String, val: Val) {
+        self.env.define(sym, val);
+    }
+
+    // changes the value of an existing binding, unlike `set`
+    pub fn assign(&mut self, sym: String, val: Val) -> Result<()> {
+        let (mut env, mut name) = (self.env.clone(), sym.as_str());
+        loop {
+            if env.assign(name, val.clone()) {
+                return Ok(());
+            }
+            match self.macros.origin(name) {
+                Some((origin, original)) => (env, name) = (origin, original),
+                None => return Err(Error::undefined(name)),
+            }
+        }
+    }
+
+    // a name introduced by a `syntax-rules` template that isn't bound where the expansion ended up
+    // means what it meant where the macro was defined
+    pub fn get(&self, sym: String) -> Result<Val> {
+        let (mut env, mut name) = (self.env.clone(), sym.as_str());
+        loop {
+            if let Some(val) = env.get(name) {
+                return Ok(val);
+            }
+            match self.macros.origin(name) {
+                Some((origin, original)) => (env, name) = (origin, original),
+                None => return Err(Error::undefined(name)),
+            }
+        }
+    }
+}
+
+pub fn err_to_string<T: std::error::Error>(err: T) -> String {
+    format!("{:?}", err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ExprKind::{Atom, Sym};
+    use Val::*;
+
+    #[test]
+    fn test_eval_atom() {
+        let input: [Expr; 3] = [Atom(Int(10)).into(), Atom(Float(0.0)).into(), Atom(Str(String::from("test"))).into()];
+        let mut sys = System::new();
+        let result = input
+            .iter()
+            .map(|val| sys.eval(val).unwrap())
+            .collect::<Vec<_>>();
+        let expected = vec![Int(10), Float(0.0), Str(String::from("test"))];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_eval_sym() {
+        let input: [Expr; 2] = [Sym(String::from("ldaslidhis")).into(), Sym(String::from("hdlhahdhiualid")).into()];
+        let mut sys = System::new();
+        sys.set(String::from("ldaslidhis"), Int(87973003)).unwrap();
+        sys.set(String::from("hdlhahdhiualid"), Str(String::from("hhidy98y"))).unwrap();
+
+        let result = input
+            .iter()
+            .map(|val| sys.eval(val).unwrap())
+            .collect::<Vec<_>>();
+        let expected = vec![Int(87973003), Str(String::from("hhidy98y"))];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_eval_sym_undefined() {
+        let input = Sym(String::from("aldsdhasdj")).into();
+        let mut sys = System::new();
+        sys.eval(&input).unwrap();
+    }
+
+    fn first(_: &mut System, args: Vec<Val>) -> Result<Val> {
+        Ok(args[0].clone())
+    }
+
+    fn count(_: &mut System, args: Vec<Val>) -> Result<Val> {
+        Ok(Int(args.len() as i64))
+    }
+
+    #[test]
+    fn test_eval_func_builtin() {
+        let mut sys = System::new();
+        sys.define_builtin("first", Arity::AtLeast(1), first).unwrap();
+        sys.define_builtin("count", Arity::AtLeast(0), count).unwrap();
+
+        let expr = try_parse_expr(r#"(count (first 1 2) (count) (first "a"))"#).unwrap();
+        assert_eq!(sys.eval(&expr).unwrap(), Int(3));
+    }
+
+    #[test]
+    fn test_eval_func_user() {
+        let mut sys = System::new();
+        sys.define_builtin("first", Arity::AtLeast(1), first).unwrap();
+        sys.set(String::from("x"), Int(1)).unwrap();
+        sys.set(String::from("y"), Int(2)).unwrap();
+        // `x` is shadowed by the parameter, `y` comes from the globals
+        sys.define_func("pick", vec![String::from("x")], try_parse_expr("(first y x)").unwrap()).unwrap();
+        sys.define_func("outer", vec![String::from("y")], try_parse_expr("(pick y)").unwrap()).unwrap();
+
+        let expr = try_parse_expr("(outer 10)").unwrap();
+        assert_eq!(sys.eval(&expr).unwrap(), Int(2));
+        // parameters don't leak out of the call
+        assert_eq!(sys.get(String::from("x")).unwrap(), Int(1));
+    }
+
+    #[test]
+    fn test_redefine() {
+        let mut sys = System::new();
+        sys.define_builtin("first", Arity::Exact(1), first).unwrap();
+        sys.set(String::from("x"), Int(1)).unwrap();
+
+        // `define` in code replaces an earlier definition, builtins included
+        let eval = |sys: &mut System, src| sys.eval(&try_parse_expr(src).unwrap()).unwrap();
+        assert_eq!(eval(&mut sys, "(define x 2)"), Int(2));
+        assert_eq!(eval(&mut sys, "x"), Int(2));
+        eval(&mut sys, "(define (first x) (+ x 1))");
+        assert_eq!(eval(&mut sys, "(first 1)"), Int(2));
+
+        // ... but defining from Rust doesn't
+        assert!(sys.set(String::from("x"), Int(3)).is_err());
+        assert!(sys.define_builtin("first", Arity::Exact(1), first).is_err());
+        assert_eq!(eval(&mut sys, "(+ x (first 1))"), Int(4));
+    }
+
+    #[test]
+    fn test_eval_func_errors() {
+        let mut sys = System::new();
+        sys.define_builtin("first", Arity::Exact(1), first).unwrap();
+        sys.set(String::from("x"), Int(1)).unwrap();
+
+        let input = ["(x 1)", "(nope 1)", "(first 1 2)", "(first)", "((first 1) 2)", "()"];
+        for src in input {
+            let expr = try_parse_expr(src).unwrap();
+            assert!(sys.eval(&expr).is_err(), "`{}` should fail", src);
+        }
+
+        let err = sys.eval(&try_parse_expr("(first 1 2)").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "function `first` expects 1 argument, got 2");
+        assert!(sys.define_builtin("first", Arity::Exact(1), first).is_err());
+    }
+
+    #[test]
+    fn test_eval_error_kinds() {
+        let mut sys = System::new();
+        sys.define_builtin("first", Arity::Exact(1), first).unwrap();
+        sys.set(String::from("x"), Int(1)).unwrap();
+
+        let eval = |sys: &mut System, src| sys.eval(&try_parse_expr(src).unwrap()).unwrap_err().kind().clone();
+        assert!(matches!(eval(&mut sys, "(nope 1)"), ErrorKind::UndefinedName { name, .. } if name == "nope"));
+        assert!(matches!(
+            eval(&mut sys, "(first 1 2)"),
+            ErrorKind::ArityError { func, expected: Arity::Exact(1), got: 2, .. } if func == "first"
+        ));
+        assert!(matches!(eval(&mut sys, "(x 1)"), ErrorKind::NotCallable { got: Int(1), .. }));
+        assert!(matches!(eval(&mut sys, "(if)"), ErrorKind::SyntaxError { .. }));
+        assert!(matches!(sys.set(String::from("x"), Int(2)).unwrap_err().kind(), ErrorKind::Reassignment { .. }));
+        assert!(matches!(sys.get(String::from("y")).unwrap_err().kind(), ErrorKind::UndefinedName { .. }));
+        assert!(matches!(try_parse_expr("(f").unwrap_err().kind(), ErrorKind::ParseError { .. }));
+
+        // every call that recurses through the evaluator carries a `Result`, so errors are kept to a pointer
+        assert_eq!(std::mem::size_of::<Error>(), std::mem::size_of::<usize>());
+    }
+
+    #[test]
+    fn test_eval_quote() {
+        let mut sys = System::new();
+        let input = ["'(1 (2.0 \"x\"))", "(quote (1 (2.0 \"x\")))", "(quote 5)", "(quote ())"];
+        let result = input
+            .iter()
+            .map(|src| sys.eval(&try_parse_expr(src).unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        let nested = Val::List(vec![Int(1), Val::List(vec![Float(2.0), Str(String::from("x"))].into())].into());
+        let expected = vec![nested.clone(), nested, Int(5), Val::List(vec![].into())];
+        assert_eq!(result, expected);
+
+        // symbols in quoted code become symbol values
+        let code = sys.eval(&try_parse_expr("(quote (1 (+ 2 3)))").unwrap()).unwrap();
+        let expected = Val::List(vec![Int(1), Val::List(vec![Val::Sym(symbol::Symbol::new("+")), Int(2), Int(3)].into())].into());
+        assert_eq!(code, expected);
+        assert!(sys.eval(&try_parse_expr("(quote 1 2)").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_eval_error_span() {
+        let mut sys = System::new();
+        sys.define_builtin("first", Arity::Exact(1), first).unwrap();
+
+        let input = [
+            ("(+ 1 (first x))", "name `x` is undefined", 13),
+            ("(+ 1\n   (first 1 2))", "function `first` expects 1 argument, got 2", 4),
+            ("(* 2 (+ 1 \"a\"))", "`+` expects numbers, got `\"a\"`", 6),
+        ];
+        for (src, message, column) in input {
+            let err = sys.eval(&try_parse_expr(src).unwrap()).unwrap_err();
+            assert_eq!((err.to_string().as_str(), err.span().column), (message, column));
+        }
+
+        let err = sys.eval(&try_parse_expr("(+ 1\n   (first 1 2))").unwrap()).unwrap_err();
+        let expected = [
+            "error: function `first` expects 1 argument, got 2",
+            " --> <input>:2:4",
+            "  |",
+            "2 |    (first 1 2))",
+            "  |    ^^^^^^^^^^^",
+        ];
+        assert_eq!(err.render(), expected.join("\n"));
+    }
+
+    #[test]
+    fn test_eval_program() {
+        let mut sys = System::new();
+        sys.define_builtin("first", Arity::Exact(1), first).unwrap();
+
+        let program = parse_program("(first 1)\n(+ 1 2)\n\n(* 2 3.0)").unwrap();
+        assert_eq!(sys.eval_program(&program).unwrap(), Float(6.0));
+        assert_eq!(sys.eval_program(&[]).unwrap(), Val::Nil);
+
+        // evaluation stops at the first error
+        let program = parse_program("(first 1) (first 1 2) (undefined)").unwrap();
+        let err = sys.eval_program(&program).unwrap_err();
+        assert_eq!(err.to_string(), "function `first` expects 1 argument, got 2");
+    }
+
+    #[test]
+    fn test_eval_scopes() {
+        let mut sys = System::new();
+        let program = parse_program(
+            "(define x 1)
+             (define (outer x)
+               (let ((y (* x 2)))
+                 (lambda (z) (list-of x y z))))
+             (define list-of (lambda args args))
+             (define f (outer 10))",
+        )
+        .unwrap();
+        sys.eval_program(&program).unwrap();
+
+        // parameters and `let` bindings shadow globals, and closures keep every enclosing scope alive
+        let expr = try_parse_expr("(f 3)").unwrap();
+        assert_eq!(sys.eval(&expr).unwrap(), Val::List(vec![Int(10), Int(20), Int(3)].into()));
+        assert_eq!(sys.get(String::from("x")).unwrap(), Int(1));
+
+        // only the globals are visible from the top level
+        let globals = sys.globals().into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+        assert!(globals.contains(&String::from("outer")) && globals.contains(&String::from("+")));
+        assert!(!globals.contains(&String::from("y")));
+        assert!(sys.get(String::from("y")).is_err());
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        let src = r#"
+            (define (count n acc) (if (= n 0) acc (count (- n 1) (+ acc 1))))
+            (define (down n) (cond ((= n 0) "done") (else (let ((m (- n 1))) (begin (down m))))))
+            (define (is-even n) (or (= n 0) (and (> n 0) (is-odd (- n 1)))))
+            (define (is-odd n) (when (> n 0) (is-even (- n 1))))
+            (list (count 1000000 0) (down 100000) (is-even 100000))"#;
+
+        // a stack this small would overflow after a few thousand nested calls
+        std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(move || {
+                let mut sys = System::new();
+                sys.define_builtin("list", Arity::AtLeast(0), |_, args| Ok(List(args.into()))).unwrap();
+                let result = sys.eval_program(&ast::parse_program(src).unwrap()).unwrap();
+                let expected = List(vec![Int(1000000), Str(String::from("done")), Bool(true)].into());
+                assert_eq!(result, expected);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    thread_local! {
+        static SCOPES: std::cell::RefCell<Vec<std::rc::Weak<Env>>> = Default::default();
+    }
+
+    fn remember_scope(sys: &mut System, _: Vec<Val>) -> Result<Val> {
+        SCOPES.with(|scopes| scopes.borrow_mut().push(Rc::downgrade(&sys.capture())));
+        Ok(Nil)
+    }
+
+    #[test]
+    fn test_scopes_released() {
+        let mut sys = System::new();
+        sys.define_builtin("remember-scope", Arity::Exact(0), remember_scope).unwrap();
+        let program = parse_program(
+            "(define (local) (define (g) 1) (remember-scope) (g))
+             (define (mutual) (letrec ((a (lambda () (b))) (b (lambda () 2))) (remember-scope) (a)))
+             (define (escape) (define (g) 3) (remember-scope) g)
+             (+ (local) (mutual))",
+        )
+        .unwrap();
+        assert_eq!(sys.eval_program(&program).unwrap(), Int(3));
+
+        // closures defined in a scope and the scope refer to each other, but nothing else refers to
+        // either once the call is over
+        let alive = || {
+            let scopes = SCOPES.with(|scopes| scopes.take());
+            scopes.iter().map(|scope| scope.upgrade().is_some()).collect::<Vec<_>>()
+        };
+        assert_eq!(alive(), vec![false, false]);
+
+        // a closure that's still reachable keeps its scope
+        let escaped = sys.eval(&try_parse_expr("(escape)").unwrap()).unwrap();
+        assert_eq!(alive(), vec![true]);
+        assert_eq!(sys.apply(&escaped, vec![]).unwrap(), Int(3));
+
+        // so does a macro, until it's replaced
+        let program = parse_program(
+            "(define (with-macro n)
+               (define (g) n)
+               (define-syntax m (syntax-rules () ((_) (g))))
+               (remember-scope)
+               (m))
+             (with-macro 1)",
+        )
+        .unwrap();
+        assert_eq!(sys.eval_program(&program).unwrap(), Int(1));
+        assert_eq!(sys.eval(&try_parse_expr("(with-macro 2)").unwrap()).unwrap(), Int(2));
+        assert_eq!(alive(), vec![false, true]);
+        assert_eq!(sys.eval(&try_parse_expr("(m)").unwrap()).unwrap(), Int(2));
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        let src = "(define (f n) (if (= n 0) 0 (+ 1 (f (- n 1)))))";
+
+        // the test thread's own stack, which is smaller than a main thread's
+        let mut sys = System::new();
+        sys.set_max_depth(200);
+        sys.eval_program(&parse_program(src).unwrap()).unwrap();
+        assert_eq!(sys.eval(&try_parse_expr("(f 50)").unwrap()).unwrap(), Int(50));
+
+        let err = sys.eval(&try_parse_expr("(f 1000000)").unwrap()).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::RecursionLimit { limit: 200, .. }));
+        // the failed call doesn't leave the system any deeper
+        assert_eq!(sys.depth, 0);
+        assert_eq!(sys.eval(&try_parse_expr("(f 10)").unwrap()).unwrap(), Int(10));
+
+        // a macro that expands to itself never reaches a function call
+        let program = parse_program("(defmacro m () '(m)) (m)").unwrap();
+        let err = sys.eval_program(&program).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::RecursionLimit { .. }));
+    }
+
+    #[test]
+    fn test_default_recursion_limit() {
+        let src = "(define (f n) (if (= n 0) 0 (+ 1 (f (- n 1)))))
+                   (define (g n) (if (= n 0) 0 (+ 1 (car (map g (cons (- n 1) '()))))))";
+
+        // the default fits the stack of a main thread
+        std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(move || {
+                let mut sys = System::new();
+                sys.eval_program(&parse_program(src).unwrap()).unwrap();
+                assert_eq!(sys.eval(&try_parse_expr("(f 300)").unwrap()).unwrap(), Int(300));
+                for call in ["(f 1000000)", "(g 1000000)"] {
+                    let err = sys.eval(&try_parse_expr(call).unwrap()).unwrap_err();
+                    assert!(matches!(err.kind(), ErrorKind::RecursionLimit { limit: DEFAULT_MAX_DEPTH, .. }));
+                }
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
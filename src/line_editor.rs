@@ -0,0 +1,446 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+const HISTORY_LIMIT: usize = 1000;
+
+pub enum Line {
+    Text(String),
+    // ctrl-c
+    Interrupted,
+    // ctrl-d on an empty line, or the end of piped input
+    Eof,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    KillToEnd,
+    KillToStart,
+    Interrupt,
+    Eof,
+    Ignored,
+}
+
+// the line being edited and where we are in the history
+#[derive(Default)]
+struct LineBuffer {
+    chars: Vec<char>,
+    cursor: usize,
+    // index into the history while browsing it, with the unfinished line saved
+    browsing: Option<(usize, Vec<char>)>,
+}
+impl LineBuffer {
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    fn replace(&mut self, chars: Vec<char>) {
+        self.cursor = chars.len();
+        self.chars = chars;
+    }
+
+    // returns the finished line once there is one
+    fn handle(&mut self, key: Key, history: &[String]) -> Option<Line> {
+        match key {
+            Key::Char(c) => {
+                self.chars.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            Key::Enter => return Some(Line::Text(self.text())),
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.chars.remove(self.cursor);
+            }
+            Key::Delete if self.cursor < self.chars.len() => {
+                self.chars.remove(self.cursor);
+            }
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(self.chars.len()),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.chars.len(),
+            Key::KillToEnd => self.chars.truncate(self.cursor),
+            Key::KillToStart => {
+                self.chars.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::Up => {
+                let index = match &self.browsing {
+                    Some((0, _)) => return None,
+                    Some((index, _)) => index - 1,
+                    None if history.is_empty() => return None,
+                    None => {
+                        self.browsing = Some((history.len(), self.chars.clone()));
+                        history.len() - 1
+                    }
+                };
+                self.browsing.as_mut().unwrap().0 = index;
+                self.replace(history[index].chars().collect());
+            }
+            Key::Down => match self.browsing.take() {
+                Some((index, unfinished)) if index + 1 < history.len() => {
+                    self.browsing = Some((index + 1, unfinished));
+                    self.replace(history[index + 1].chars().collect());
+                }
+                Some((_, unfinished)) => self.replace(unfinished),
+                None => {}
+            },
+            Key::Interrupt => return Some(Line::Interrupted),
+            Key::Eof if self.chars.is_empty() => return Some(Line::Eof),
+            _ => {}
+        }
+        None
+    }
+}
+
+pub struct LineEditor {
+    history: Vec<String>,
+    history_path: Option<PathBuf>,
+    // the terminal's settings from before raw mode, read once and put back after every line.
+    // `None` until the first line is read from a terminal.
+    terminal: Option<Option<String>>,
+}
+impl LineEditor {
+    pub fn new(history_path: Option<PathBuf>) -> Self {
+        let mut history: Vec<String> = history_path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(unescape_entry).collect())
+            .unwrap_or_default();
+        if history.len() > HISTORY_LIMIT {
+            history.drain(..history.len() - HISTORY_LIMIT);
+            // the file only grows while running, so it's cut back to the limit here
+            if let Some(path) = &history_path {
+                let text: String = history.iter().map(|entry| escape_entry(entry) + "\n").collect();
+                let _ = fs::write(path, text);
+            }
+        }
+        Self { history, history_path, terminal: None }
+    }
+
+    // remembers `entry` and appends it to the history file straight away, so that a session that
+    // gets killed keeps its history
+    pub fn add_history(&mut self, entry: &str) -> io::Result<()> {
+        let entry = entry.trim();
+        if entry.is_empty() || self.history.last().map(String::as_str) == Some(entry) {
+            return Ok(());
+        }
+        self.history.push(entry.to_string());
+        let Some(path) = &self.history_path else {
+            return Ok(());
+        };
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", escape_entry(entry))
+    }
+
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Line> {
+        // raw mode is set with `stty`, so it's only tried on unix, and terminals that don't
+        // understand escape codes would never answer `terminal_width`
+        let dumb = matches!(std::env::var("TERM").as_deref(), Err(_) | Ok("dumb" | "emacs"));
+        if !cfg!(unix) || dumb || !io::stdin().is_terminal() {
+            return read_plain_line(prompt);
+        }
+        let saved = self.terminal.get_or_insert_with(|| stty(&["-g"]).map(|saved| saved.trim().to_string()));
+        let Some(_raw) = saved.as_deref().and_then(RawMode::enable) else {
+            return read_plain_line(prompt);
+        };
+
+        let mut stdin = io::stdin().lock();
+        let mut stdout = io::stdout();
+        let width = terminal_width(&mut stdin, &mut stdout)?.unwrap_or(80);
+        let mut buffer = LineBuffer::default();
+        let mut row = 0;
+        render(&mut stdout, prompt, &buffer, width, &mut row)?;
+        loop {
+            let key = read_key(&mut stdin)?;
+            if let Some(line) = buffer.handle(key, &self.history) {
+                // leave the cursor below all of the input
+                buffer.cursor = buffer.chars.len();
+                render(&mut stdout, prompt, &buffer, width, &mut row)?;
+                write!(stdout, "\r\n")?;
+                stdout.flush()?;
+                return Ok(line);
+            }
+            render(&mut stdout, prompt, &buffer, width, &mut row)?;
+        }
+    }
+}
+
+// the history file has one entry per line, so the newlines of multi-line entries are escaped
+fn escape_entry(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape_entry(line: &str) -> String {
+    let mut entry = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                entry.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                entry.push('\\');
+                chars.next();
+            }
+            _ => entry.push(c),
+        }
+    }
+    entry
+}
+
+// the prompt goes to stderr, so it doesn't end up in the output when that's piped too
+fn read_plain_line(prompt: &str) -> io::Result<Line> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Ok(Line::Eof);
+    }
+    Ok(Line::Text(line.trim_end_matches(['\n', '\r']).to_string()))
+}
+
+// redraws the prompt and the line, which wraps onto as many rows as it needs. `row` is the row the
+// cursor was left on, counted from the prompt's, and is updated for the next redraw.
+fn render(out: &mut impl Write, prompt: &str, buffer: &LineBuffer, width: usize, row: &mut usize) -> io::Result<()> {
+    if *row > 0 {
+        write!(out, "\x1b[{}A", row)?;
+    }
+    // a recalled multi-line entry is shown on one line, with a mark where each newline is
+    write!(out, "\r\x1b[J{}{}", prompt, buffer.text().replace('\n', "\u{21b5}"))?;
+
+    let prompt_len = prompt.chars().count();
+    let end = prompt_len + buffer.chars.len();
+    // after filling the last column the cursor stays there until something else is written
+    if end > 0 && end.is_multiple_of(width) {
+        write!(out, "\r\n")?;
+    }
+    let cursor = prompt_len + buffer.cursor;
+    let up = end / width - cursor / width;
+    if up > 0 {
+        write!(out, "\x1b[{}A", up)?;
+    }
+    write!(out, "\r")?;
+    if !cursor.is_multiple_of(width) {
+        write!(out, "\x1b[{}C", cursor % width)?;
+    }
+    *row = cursor / width;
+    out.flush()
+}
+
+// asks the terminal where the cursor ends up after moving it as far right as it goes. The terminal
+// has to be in raw mode for its answer, `ESC [ row ; column R`, to be read.
+fn terminal_width(input: &mut impl Read, out: &mut impl Write) -> io::Result<Option<usize>> {
+    write!(out, "\r\x1b[999C\x1b[6n")?;
+    out.flush()?;
+    let mut reply = vec![];
+    while reply.len() < 32 {
+        match read_byte(input)? {
+            Some(b'R') | None => break,
+            Some(byte) => reply.push(byte),
+        }
+    }
+    write!(out, "\r")?;
+    let reply = String::from_utf8_lossy(&reply);
+    let column = reply.strip_prefix("\x1b[").and_then(|pos| pos.split_once(';')).map(|(_, column)| column);
+    Ok(column.and_then(|column| column.parse().ok()).filter(|width| *width > 0))
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match input.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+fn read_key(input: &mut impl Read) -> io::Result<Key> {
+    let Some(byte) = read_byte(input)? else {
+        return Ok(Key::Eof);
+    };
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        0x01 => Key::Home,
+        0x05 => Key::End,
+        0x02 => Key::Left,
+        0x06 => Key::Right,
+        0x10 => Key::Up,
+        0x0e => Key::Down,
+        0x0b => Key::KillToEnd,
+        0x15 => Key::KillToStart,
+        0x03 => Key::Interrupt,
+        0x04 => Key::Eof,
+        0x1b => match (read_byte(input)?, read_byte(input)?) {
+            (Some(b'[' | b'O'), Some(b'A')) => Key::Up,
+            (Some(b'[' | b'O'), Some(b'B')) => Key::Down,
+            (Some(b'[' | b'O'), Some(b'C')) => Key::Right,
+            (Some(b'[' | b'O'), Some(b'D')) => Key::Left,
+            (Some(b'[' | b'O'), Some(b'H')) => Key::Home,
+            (Some(b'[' | b'O'), Some(b'F')) => Key::End,
+            (Some(b'['), Some(b'3')) => match read_byte(input)? {
+                Some(b'~') => Key::Delete,
+                _ => Key::Ignored,
+            },
+            _ => Key::Ignored,
+        },
+        byte if byte < 0x20 => Key::Ignored,
+        byte => {
+            // the rest of a multi-byte utf-8 sequence
+            let len = match byte {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            };
+            let mut bytes = vec![byte];
+            for _ in 1..len {
+                bytes.extend(read_byte(input)?);
+            }
+            match std::str::from_utf8(&bytes).ok().and_then(|s| s.chars().next()) {
+                Some(c) => Key::Char(c),
+                None => Key::Ignored,
+            }
+        }
+    };
+    Ok(key)
+}
+
+// puts the terminal into raw mode until dropped, then back to the `saved` settings
+struct RawMode<'a> {
+    saved: &'a str,
+}
+impl<'a> RawMode<'a> {
+    fn enable(saved: &'a str) -> Option<Self> {
+        stty(&["raw", "-echo"])?;
+        Some(Self { saved })
+    }
+}
+impl Drop for RawMode<'_> {
+    fn drop(&mut self) {
+        stty(&[self.saved]);
+    }
+}
+
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_keys(buffer: &mut LineBuffer, keys: &[Key], history: &[String]) -> Option<String> {
+        for key in keys {
+            if let Some(Line::Text(text)) = buffer.handle(*key, history) {
+                return Some(text);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn test_line_buffer_editing() {
+        use Key::*;
+
+        let mut buffer = LineBuffer::default();
+        let keys = [
+            Char('b'), Char('c'), Home, Char('('), End, Char(')'), Left, Backspace, Char('d'), Enter,
+        ];
+        assert_eq!(type_keys(&mut buffer, &keys, &[]), Some(String::from("(bd)")));
+
+        let mut buffer = LineBuffer::default();
+        let keys = [Char('a'), Char('b'), Char('c'), Left, KillToEnd, Home, Delete, Enter];
+        assert_eq!(type_keys(&mut buffer, &keys, &[]), Some(String::from("b")));
+    }
+
+    #[test]
+    fn test_line_buffer_history() {
+        use Key::*;
+
+        let history = [String::from("(first)"), String::from("(second)")];
+        let mut buffer = LineBuffer::default();
+        let keys = [Char('x'), Up, Up, Up, Down, Enter];
+        assert_eq!(type_keys(&mut buffer, &keys, &history), Some(String::from("(second)")));
+
+        let mut buffer = LineBuffer::default();
+        let keys = [Char('x'), Up, Down, Char('y'), Enter];
+        assert_eq!(type_keys(&mut buffer, &keys, &history), Some(String::from("xy")));
+    }
+
+    #[test]
+    fn test_render_wrapped() {
+        let render_to_string = |buffer: &LineBuffer, row: &mut usize| {
+            let mut out = vec![];
+            render(&mut out, "> ", buffer, 4, row).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        // "> abcdefg" wraps onto three rows of 4 columns
+        let mut buffer = LineBuffer::default();
+        buffer.replace("abcdefg".chars().collect());
+        let mut row = 0;
+        assert_eq!(render_to_string(&buffer, &mut row), "\r\x1b[J> abcdefg\r\x1b[1C");
+        assert_eq!(row, 2);
+
+        // the redraw starts from the prompt's row, and the cursor goes back to the first row
+        buffer.cursor = 1;
+        assert_eq!(render_to_string(&buffer, &mut row), "\x1b[2A\r\x1b[J> abcdefg\x1b[2A\r\x1b[3C");
+        assert_eq!(row, 0);
+
+        // filling the last column of a row moves onto the next one
+        buffer.replace("ab".chars().collect());
+        assert_eq!(render_to_string(&buffer, &mut row), "\r\x1b[J> ab\r\n\r");
+        assert_eq!(row, 1);
+    }
+
+    #[test]
+    fn test_terminal_width() {
+        let mut out = vec![];
+        assert_eq!(terminal_width(&mut "\x1b[12;97R".as_bytes(), &mut out).unwrap(), Some(97));
+        assert_eq!(terminal_width(&mut "x".as_bytes(), &mut out).unwrap(), None);
+    }
+
+    #[test]
+    fn test_history_file() {
+        let path = std::env::temp_dir().join(format!("alisp_history_test_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut editor = LineEditor::new(Some(path.clone()));
+        editor.add_history("(f 1)").unwrap();
+        editor.add_history("(f 1)").unwrap();
+        editor.add_history("(define s \"a\n  b\") ; c:\\n\n(g\n  2)\n").unwrap();
+
+        // written as they're added, without waiting for the session to end
+        let editor = LineEditor::new(Some(path.clone()));
+        let expected = vec![String::from("(f 1)"), String::from("(define s \"a\n  b\") ; c:\\n\n(g\n  2)")];
+        assert_eq!(editor.history, expected);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        fs::write(&path, "(x)\n".repeat(HISTORY_LIMIT + 5)).unwrap();
+        let editor = LineEditor::new(Some(path.clone()));
+        assert_eq!(editor.history.len(), HISTORY_LIMIT);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), HISTORY_LIMIT);
+        fs::remove_file(path).unwrap();
+    }
+}
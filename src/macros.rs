@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use crate::ast::{quote, to_expr, Expr, ExprKind, Val};
+use crate::env::Env;
+use crate::error::{Error, Result};
+use crate::span::Span;
+use crate::syntax_rules::{original_name, SyntaxRules};
+use crate::{special_forms, Function, System};
+
+#[derive(Clone)]
+pub enum Macro {
+    // `defmacro`, a function from code to code
+    Procedural(Rc<Function>),
+    // `define-syntax`, which rewrites code by pattern and renames what it introduces
+    Rules(Rc<SyntaxRules>),
+}
+impl Macro {
+    // calls the macro with its arguments as data, and turns what it returns back into code
+    pub fn expand(&self, sys: &mut System, args: &[Expr], span: &Span) -> Result<Expr> {
+        let args = args.iter().map(quote).collect::<Vec<_>>();
+        let code = match self {
+            Macro::Procedural(func) => sys.apply(&Val::Func(func.clone()), args)?,
+            Macro::Rules(rules) => rules.expand(sys, &args)?,
+        };
+        Ok(to_expr(&code, span))
+    }
+
+    // the scope the macro was defined in, which it keeps alive
+    fn scope(&self) -> Option<Rc<Env>> {
+        match self {
+            Macro::Procedural(func) => match func.as_ref() {
+                Function::Lambda { env, .. } => Some(env.clone()),
+                Function::Builtin { .. } => None,
+            },
+            Macro::Rules(rules) => Some(rules.scope().clone()),
+        }
+    }
+}
+
+// macros are global, and live apart from the values
+#[derive(Default)]
+pub struct Macros {
+    table: HashMap<String, Macro>,
+    // the scopes `syntax-rules` macros were defined in, by the number renamed names refer to them
+    // by. The macros keep their scopes alive, so a scope goes away once no macro or closure needs it.
+    scopes: HashMap<usize, Weak<Env>>,
+    next_scope: usize,
+    expansions: usize,
+}
+impl Macros {
+    pub fn define(&mut self, name: String, mac: Macro) -> Result<()> {
+        match self.table.try_insert(name.clone(), mac) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error::reassignment(&name)),
+        }
+    }
+
+    // like `define`, but replaces any macro of the same name. The scope the old one was defined in
+    // may be left with nothing but its own closures referring to it, like a call that's over.
+    pub fn redefine(&mut self, name: String, mac: Macro) {
+        let Some(old) = self.table.insert(name, mac) else {
+            return;
+        };
+        let scope = old.scope();
+        drop(old);
+        if let Some(scope) = scope {
+            scope.release();
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Macro> {
+        self.table.get(name).cloned()
+    }
+
+    // a number that renamed names can refer to `env` by. Numbers aren't reused, so a name renamed
+    // for a scope that's gone never finds another one.
+    pub fn add_scope(&mut self, env: &Rc<Env>) -> usize {
+        let env = Rc::downgrade(env);
+        if let Some((id, _)) = self.scopes.iter().find(|(_, scope)| scope.ptr_eq(&env)) {
+            return *id;
+        }
+        self.scopes.retain(|_, scope| scope.strong_count() > 0);
+        self.next_scope += 1;
+        self.scopes.insert(self.next_scope, env);
+        self.next_scope
+    }
+
+    pub fn next_expansion(&mut self) -> usize {
+        self.expansions += 1;
+        self.expansions
+    }
+
+    // the scope a name introduced by a `syntax-rules` template is looked up in when it isn't bound
+    // where the expansion ended up, along with its original name
+    pub fn origin<'a>(&self, name: &'a str) -> Option<(Rc<Env>, &'a str)> {
+        let (original, scope) = original_name(name)?;
+        Some((self.scopes.get(&scope)?.upgrade()?, original))
+    }
+}
+
+// expands `expr` once if it's a call to a macro
+pub fn expand_1(sys: &mut System, expr: &Expr) -> Result<Option<Expr>> {
+    let ExprKind::Func(name, args) = &expr.kind else {
+        return Ok(None);
+    };
+    if special_forms::lookup(name).is_some() {
+        return Ok(None);
+    }
+    match sys.lookup_macro(name) {
+        Some(mac) => mac.expand(sys, args, &expr.span).map(Some),
+        None => Ok(None),
+    }
+}
+
+// expands `expr` until it isn't a call to a macro any more, the forms inside it are left alone
+pub fn expand(sys: &mut System, expr: &Expr) -> Result<Expr> {
+    let mut expr = expr.clone();
+    while let Some(expanded) = expand_1(sys, &expr)? {
+        expr = expanded;
+    }
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_program;
+    use Val::*;
+
+    fn eval(src: &str) -> Result<Val> {
+        System::new().eval_program(&parse_program(src)?)
+    }
+
+    fn sym(name: &str) -> Val {
+        Sym(crate::symbol::Symbol::new(name))
+    }
+
+    #[test]
+    fn test_defmacro() {
+        let input = [
+            ("(defmacro my-if (c a b) `(cond (,c ,a) (else ,b))) (my-if (< 1 2) 10 20)", Int(10)),
+            // the arguments are passed unevaluated, so the branch not taken never runs
+            ("(defmacro my-if (c a b) `(cond (,c ,a) (else ,b))) (my-if (< 2 1) (undefined) 20)", Int(20)),
+            ("(defmacro my-when (test &rest body) `(if ,test (begin ,@body))) (my-when 1 2 3)", Int(3)),
+            ("(defmacro swap! (a b) `(let ((tmp ,a)) (set! ,a ,b) (set! ,b tmp))) (define x 1) (define y 2) (swap! x y) (- x y)", Int(1)),
+            // macros can use other macros, and functions defined before they're expanded
+            (
+                "(defmacro twice (e) `(begin ,e ,e)) (defmacro inc! (v) `(set! ,v (+ ,v 1)))
+                 (define n 0) (twice (inc! n)) n",
+                Int(2),
+            ),
+            ("(defmacro quoted (x) `',x) (quoted (a b))", List(vec![sym("a"), sym("b")].into())),
+            ("(defmacro m () 'x) (macroexpand '(m))", sym("x")),
+            // a later definition replaces the macro
+            ("(defmacro m () 1) (defmacro m () 2) (m)", Int(2)),
+            // maps and sets in the expansion are evaluated like any other code
+            ("(defmacro m (x) x) (m {:a (+ 1 2)})", Map([(Keyword(crate::symbol::Symbol::new("a")), Int(3))].into())),
+            ("(defmacro m (x) x) (m #{(+ 1 2)})", Set([Int(3)].into())),
+        ];
+        for (src, expected) in input {
+            assert_eq!(eval(src).unwrap(), expected, "`{}`", src);
+        }
+
+        // macros aren't values
+        assert!(eval("(defmacro m (x) x) (map m '(1))").is_err());
+        assert!(eval("(defmacro m (x) x) (m)").is_err());
+        assert!(eval("(defmacro m)").is_err());
+
+        // special forms would shadow the macro
+        let err = eval("(defmacro when (x) x)").unwrap_err();
+        assert!(matches!(err.kind(), crate::ErrorKind::SyntaxError { .. }));
+    }
+
+    #[test]
+    fn test_macroexpand() {
+        let mut sys = System::new();
+        let src = "
+            (defmacro my-unless (test &rest body) `(if ,test 0 (begin ,@body)))
+            (defmacro my-not (x) `(my-unless ,x 1))";
+        sys.eval_program(&parse_program(src).unwrap()).unwrap();
+
+        let mut expand = |src: &str| sys.eval_program(&parse_program(src).unwrap()).unwrap();
+        let expected = List(vec![sym("my-unless"), sym("x"), Int(1)].into());
+        assert_eq!(expand("(macroexpand-1 '(my-not x))"), expected);
+
+        let expected = List(vec![sym("if"), sym("x"), Int(0), List(vec![sym("begin"), Int(1)].into())].into());
+        assert_eq!(expand("(macroexpand '(my-not x))"), expected);
+
+        // anything that isn't a macro call is returned as it is
+        assert_eq!(expand("(macroexpand '(+ 1 2))"), List(vec![sym("+"), Int(1), Int(2)].into()));
+        assert_eq!(expand("(macroexpand-1 5)"), Int(5));
+    }
+}
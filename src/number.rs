@@ -0,0 +1,379 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Sub};
+use std::str::FromStr;
+
+use crate::ast::Val;
+use crate::bigint::BigInt;
+use crate::error::{Error, Result};
+use crate::span::Span;
+
+// an exact fraction, always in lowest terms with a positive denominator
+#[derive(Clone, PartialEq, Eq)]
+pub struct Rational {
+    num: BigInt,
+    den: BigInt,
+}
+
+impl Rational {
+    // panics if `den` is zero
+    pub fn new(num: BigInt, den: BigInt) -> Self {
+        assert!(!den.is_zero(), "Rational with a zero denominator");
+        let gcd = num.gcd(&den);
+        let (mut num, mut den) = (num.div_rem(&gcd).0, den.div_rem(&gcd).0);
+        if den.is_negative() {
+            (num, den) = (-&num, -&den);
+        }
+        Rational { num, den }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.num.is_zero()
+    }
+
+    // rounded towards negative infinity
+    pub fn floor(&self) -> BigInt {
+        let (quotient, remainder) = self.num.div_rem(&self.den);
+        if remainder.is_negative() { &quotient - &BigInt::from(1) } else { quotient }
+    }
+
+    // the nearest float. The fraction is scaled by a power of two so the integer part of the
+    // quotient has at least 64 bits, and an extra lowest bit records whether anything was left
+    // over, so converting that to a float is the only rounding. Below the normal floats the scale
+    // stops one bit past the smallest subnormal, and the rounding happens when scaling back instead.
+    pub fn to_f64(&self) -> f64 {
+        let shift = (64 + self.den.bit_len() as i64 - self.num.bit_len() as i64).min(1075);
+        let (num, den) = match shift {
+            0.. => (&self.num.abs() << shift as u64, self.den.clone()),
+            _ => (self.num.abs(), &self.den << shift.unsigned_abs()),
+        };
+        let (quotient, remainder) = num.div_rem(&den);
+        let quotient = &(&quotient << 1) + &BigInt::from(!remainder.is_zero() as i64);
+        let magnitude = scale(quotient.to_f64(), -shift - 1);
+        if self.num.is_negative() { -magnitude } else { magnitude }
+    }
+
+    // the exact value of a finite float, which is always a fraction with a power of two below it
+    pub fn from_f64(f: f64) -> Option<Self> {
+        if !f.is_finite() {
+            return None;
+        }
+        let bits = f.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i32;
+        let mantissa = bits & ((1 << 52) - 1);
+        // subnormals have no implicit leading bit
+        let (mantissa, exponent) = match exponent {
+            0 => (mantissa, -1074),
+            _ => (mantissa | 1 << 52, exponent - 1075),
+        };
+        let mut num = BigInt::from(mantissa as i64);
+        if f.is_sign_negative() {
+            num = -&num;
+        }
+        let power = BigInt::from(2).pow(exponent.unsigned_abs());
+        Some(match exponent {
+            0.. => Rational::from(&num * &power),
+            _ => Rational::new(num, power),
+        })
+    }
+}
+
+// `f * 2^exp`, in steps small enough that the power of two is never infinite or zero
+fn scale(mut f: f64, mut exp: i64) -> f64 {
+    while exp.abs() > 1000 {
+        f *= 2f64.powi(1000 * exp.signum() as i32);
+        exp -= 1000 * exp.signum();
+    }
+    f * 2f64.powi(exp as i32)
+}
+
+impl From<BigInt> for Rational {
+    fn from(num: BigInt) -> Self {
+        Rational { num, den: BigInt::from(1) }
+    }
+}
+
+// with `gcd` the gcd of the denominators, the sum can only have factors of `gcd` to cancel, so the
+// gcd of the full numerator and denominator is never needed (Knuth, TAOCP vol. 2, 4.5.1)
+impl Add for &Rational {
+    type Output = Rational;
+
+    fn add(self, other: &Rational) -> Rational {
+        let gcd = self.den.gcd(&other.den);
+        let (den, other_den) = (self.den.div_rem(&gcd).0, other.den.div_rem(&gcd).0);
+        let num = &(&self.num * &other_den) + &(&other.num * &den);
+        if num.is_zero() {
+            return Rational::from(num);
+        }
+        let common = num.gcd(&gcd);
+        Rational { num: num.div_rem(&common).0, den: &den * &other.den.div_rem(&common).0 }
+    }
+}
+
+impl Sub for &Rational {
+    type Output = Rational;
+
+    fn sub(self, other: &Rational) -> Rational {
+        self + &Rational { num: -&other.num, den: other.den.clone() }
+    }
+}
+
+impl Mul for &Rational {
+    type Output = Rational;
+
+    fn mul(self, other: &Rational) -> Rational {
+        Rational::new(&self.num * &other.num, &self.den * &other.den)
+    }
+}
+
+// panics if `other` is zero
+impl Div for &Rational {
+    type Output = Rational;
+
+    fn div(self, other: &Rational) -> Rational {
+        Rational::new(&self.num * &other.den, &self.den * &other.num)
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.num * &other.den).cmp(&(&other.num * &self.den))
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.num, self.den)
+    }
+}
+impl fmt::Debug for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// exact numbers are kept in the smallest representation that holds them: an `Int` if they're whole
+// and fit in 64 bits, then a `Big` if they're whole, and a `Ratio` otherwise
+impl From<Rational> for Val {
+    fn from(ratio: Rational) -> Self {
+        if ratio.den == BigInt::from(1) {
+            Val::from(ratio.num)
+        } else {
+            Val::Ratio(ratio)
+        }
+    }
+}
+
+impl From<BigInt> for Val {
+    fn from(big: BigInt) -> Self {
+        match big.to_i64() {
+            Some(i) => Val::Int(i),
+            None => Val::Big(big),
+        }
+    }
+}
+
+// `Some` for the exact numbers
+pub fn to_rational(val: &Val) -> Option<Rational> {
+    match val {
+        Val::Int(i) => Some(Rational::from(BigInt::from(*i))),
+        Val::Big(big) => Some(Rational::from(big.clone())),
+        Val::Ratio(ratio) => Some(ratio.clone()),
+        _ => None,
+    }
+}
+
+// reads a numeric literal, or gives `None` if `src` isn't one. Every number may have a sign, and
+// digits may be grouped with single underscores between them, as in `1_000_000`. Whole numbers are
+// decimal, `0x` hex or `0b` binary, and don't have leading zeros; a ratio is two decimal whole
+// numbers with a `/` between them; a float needs a point or an exponent, as in `1.5`, `1.`, `.5`,
+// `1e10` and `1.5e-3`, or is `inf` or `nan`, with the Scheme spellings `inf.0` and `nan.0`.
+pub fn parse_number(src: &str) -> Option<Result<Val>> {
+    let unsigned = src.strip_prefix(['+', '-']).unwrap_or(src);
+    let sign = &src[..src.len() - unsigned.len()];
+    match unsigned {
+        "inf" | "inf.0" if sign == "-" => return Some(Ok(Val::Float(f64::NEG_INFINITY))),
+        "inf" | "inf.0" => return Some(Ok(Val::Float(f64::INFINITY))),
+        // a signed `nan` would be a different map key
+        "nan" | "nan.0" => return Some(Ok(Val::Float(f64::NAN))),
+        _ => {}
+    }
+
+    let prefixed = match unsigned.get(..2) {
+        Some("0x" | "0X") => Some(16),
+        Some("0b" | "0B") => Some(2),
+        _ => None,
+    };
+    if let Some(radix) = prefixed {
+        let digits = &unsigned[2..];
+        let valid = !digits.is_empty() && digit_run(digits, radix) == digits.len();
+        return valid.then(|| Ok(parse_int(sign, digits, radix)));
+    }
+
+    let whole = &unsigned[..digit_run(unsigned, 10)];
+    if !is_whole(whole) && !whole.is_empty() {
+        return None;
+    }
+    let mut rest = &unsigned[whole.len()..];
+    if let Some(den) = rest.strip_prefix('/') {
+        if !is_whole(whole) || !is_whole(den) || digit_run(den, 10) != den.len() {
+            return None;
+        }
+        let den = BigInt::from_str_radix(&den.replace('_', ""), 10).unwrap();
+        if den.is_zero() {
+            return Some(Err(Error::parse(format!("division by zero in `{}`", src), &Span::default())));
+        }
+        let num = BigInt::from_str_radix(&format!("{}{}", sign, whole.replace('_', "")), 10).unwrap();
+        return Some(Ok(Val::from(Rational::new(num, den))));
+    }
+
+    let point = rest.starts_with('.');
+    let mut fraction = "";
+    if point {
+        fraction = &rest[1..1 + digit_run(&rest[1..], 10)];
+        rest = &rest[1 + fraction.len()..];
+    }
+    if whole.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    let exponent = rest.starts_with(['e', 'E']);
+    if exponent {
+        let digits = rest[1..].strip_prefix(['+', '-']).unwrap_or(&rest[1..]);
+        if digits.is_empty() || digit_run(digits, 10) != digits.len() {
+            return None;
+        }
+        rest = "";
+    }
+    if !rest.is_empty() {
+        return None;
+    }
+
+    if point || exponent {
+        f64::from_str(&src.replace('_', "")).ok().map(|f| Ok(Val::Float(f)))
+    } else {
+        Some(Ok(parse_int(sign, whole, 10)))
+    }
+}
+
+// the length of the digits in `radix` at the start of `src`, with single underscores between them
+fn digit_run(src: &str, radix: u32) -> usize {
+    let is_digit = |b: Option<&u8>| b.is_some_and(|b| char::from(*b).is_digit(radix));
+    let bytes = src.as_bytes();
+    let mut len = 0;
+    while is_digit(bytes.get(len)) || (len > 0 && bytes.get(len) == Some(&b'_') && is_digit(bytes.get(len + 1))) {
+        len += 1;
+    }
+    len
+}
+
+fn is_whole(digits: &str) -> bool {
+    digits == "0" || (!digits.is_empty() && !digits.starts_with('0'))
+}
+
+// digits in `radix` after `sign`, as an `Int` if they fit in one
+fn parse_int(sign: &str, digits: &str, radix: u32) -> Val {
+    let digits = format!("{}{}", sign, digits.replace('_', ""));
+    match i64::from_str_radix(&digits, radix) {
+        Ok(i) => Val::Int(i),
+        Err(_) => Val::Big(BigInt::from_str_radix(&digits, radix).unwrap()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ratio(num: i64, den: i64) -> Rational {
+        Rational::new(BigInt::from(num), BigInt::from(den))
+    }
+
+    #[test]
+    fn test_rational() {
+        assert_eq!(ratio(2, -4), ratio(-1, 2));
+        assert_eq!(&ratio(1, 3) + &ratio(1, 6), ratio(1, 2));
+        assert_eq!(&ratio(1, 3) - &ratio(1, 2), ratio(-1, 6));
+        assert_eq!(&ratio(2, 3) * &ratio(3, 4), ratio(1, 2));
+        assert_eq!(&ratio(1, 3) / &ratio(2, 3), ratio(1, 2));
+        assert!(ratio(1, 3) < ratio(1, 2) && ratio(-1, 2) < ratio(-1, 3));
+        assert_eq!((ratio(7, 2).floor(), ratio(-7, 2).floor()), (BigInt::from(3), BigInt::from(-4)));
+
+        assert_eq!(Rational::from_f64(0.5), Some(ratio(1, 2)));
+        assert_eq!(Rational::from_f64(-3.0), Some(ratio(-3, 1)));
+        assert_eq!(Rational::from_f64(0.1).map(|r| r.to_f64()), Some(0.1));
+        assert_eq!(Rational::from_f64(f64::NAN), None);
+
+        // both parts are too big for a float, but the fraction isn't
+        let huge = BigInt::from(10).pow(400);
+        assert_eq!(Rational::new(huge.clone(), &huge + &BigInt::from(1)).to_f64(), 1.0);
+        assert_eq!(Rational::new(&huge * &BigInt::from(3), huge.clone()).to_f64(), 3.0);
+        assert_eq!(Rational::from(huge).to_f64(), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_rational_to_f64() {
+        let big = |src: &str| BigInt::from_str_radix(src, 10).unwrap();
+        let power = |exp| BigInt::from(2).pow(exp);
+        // the expected values are the correctly rounded ones, from Python's `float(Fraction(n, d))`
+        let input = [
+            (big("497930167289542051124733566112391"), big("513496474603100761"), 969685658843927.9),
+            (BigInt::from(1), BigInt::from(3), 0.3333333333333333),
+            (BigInt::from(-1), BigInt::from(10), -0.1),
+            // halfway between two floats rounds to the even one
+            (&power(53) + &BigInt::from(1), BigInt::from(1), 9007199254740992.0),
+            (&power(53) + &BigInt::from(3), BigInt::from(1), 9007199254740996.0),
+            // ... but anything past halfway rounds up
+            (
+                &(&(&power(53) + &BigInt::from(1)) * &BigInt::from(3).pow(40)) + &BigInt::from(1),
+                BigInt::from(3).pow(40),
+                9007199254740994.0,
+            ),
+            // subnormals, where halfway is between multiples of the smallest one
+            (BigInt::from(1), &BigInt::from(3) * &power(1030), 2.897231586598e-311),
+            (BigInt::from(-7), power(1076), -1e-323),
+            (BigInt::from(3), power(1075), 1e-323),
+            (BigInt::from(5), power(1075), 1e-323),
+            (BigInt::from(1), &BigInt::from(3) * &power(1074), 0.0),
+        ];
+        for (num, den, expected) in input {
+            let ratio = Rational::new(num, den);
+            assert_eq!(ratio.to_f64(), expected, "{}", ratio);
+        }
+    }
+
+    #[test]
+    fn test_rational_performance() {
+        // the harmonic sum's denominators grow to thousands of bits, and the gcds of those used to
+        // take minutes
+        let start = std::time::Instant::now();
+        let sum = (1..=2000).fold(ratio(0, 1), |sum, k| &sum + &ratio(1, k));
+        assert_eq!(sum.to_f64(), 8.178368103610282);
+        assert_eq!(&(&sum - &sum) + &ratio(0, 1), ratio(0, 1));
+        assert!(start.elapsed().as_secs() < 5, "summing took {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn test_normalization() {
+        assert!(matches!(Val::from(ratio(4, 2)), Val::Int(2)));
+        assert!(matches!(Val::from(ratio(1, 2)), Val::Ratio(_)));
+        assert!(matches!(Val::from(&BigInt::from(i64::MAX) + &BigInt::from(1)), Val::Big(_)));
+        assert!(matches!(Val::from(&BigInt::from(i64::MIN) + &BigInt::from(0)), Val::Int(i64::MIN)));
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert!(matches!(parse_number("-1_000"), Some(Ok(Val::Int(-1000)))));
+        assert!(matches!(parse_number("0b11"), Some(Ok(Val::Int(3)))));
+        assert!(matches!(parse_number("2/4"), Some(Ok(Val::Ratio(_)))));
+        assert!(matches!(parse_number("+.5e1"), Some(Ok(Val::Float(f))) if f == 5.0));
+        assert!(matches!(parse_number("1/0"), Some(Err(_))));
+        // symbols and malformed words aren't numbers
+        for src in ["", "-", "+", "...", "-x", "+inf-ity", "1_", "01", "1.5/2", "1e"] {
+            assert!(parse_number(src).is_none(), "`{}` isn't a number", src);
+        }
+    }
+}
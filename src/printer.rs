@@ -0,0 +1,90 @@
+use crate::ast::Val;
+
+// `val` as its `Display` prints it, except that lists, vectors, maps and sets too long to fit in
+// `width` columns are broken across lines, with their items lined up under the first one. Map
+// entries stay on one line each.
+pub fn pretty(val: &Val, width: usize) -> String {
+    let mut out = String::new();
+    write_pretty(&mut out, val, width);
+    out
+}
+
+fn write_pretty(out: &mut String, val: &Val, width: usize) {
+    let flat = val.to_string();
+    let start = column(out);
+    let Some((open, rows, close)) = parts(val) else {
+        out.push_str(&flat);
+        return;
+    };
+    if start + flat.chars().count() <= width {
+        out.push_str(&flat);
+        return;
+    }
+
+    out.push_str(open);
+    let indent = start + open.chars().count();
+    for (i, row) in rows.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent));
+        }
+        for (j, item) in row.iter().enumerate() {
+            if j > 0 {
+                out.push(' ');
+            }
+            write_pretty(out, item, width);
+        }
+    }
+    out.push_str(close);
+}
+
+// the brackets around a collection, and the items that go on each line when it's broken up
+fn parts(val: &Val) -> Option<(&'static str, Vec<Vec<&Val>>, &'static str)> {
+    match val {
+        Val::List(items) => Some(("(", items.iter().map(|item| vec![item]).collect(), ")")),
+        Val::Vector(items) => Some(("[", items.iter().map(|item| vec![item]).collect(), "]")),
+        Val::Map(map) => Some(("{", map.iter().map(|(k, v)| vec![k, v]).collect(), "}")),
+        Val::Set(set) => Some(("#{", set.iter().map(|item| vec![item]).collect(), "}")),
+        _ => None,
+    }
+}
+
+// of the end of the last line
+fn column(out: &str) -> usize {
+    out.rsplit('\n').next().unwrap_or("").chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{quote, try_parse_expr};
+
+    fn read(src: &str) -> Val {
+        quote(&try_parse_expr(src).unwrap())
+    }
+
+    #[test]
+    fn test_pretty() {
+        let val = read("(define (f x) (g x [1 2 3] {:key (long list of items) :other 2}))");
+        assert_eq!(pretty(&val, 80), val.to_string());
+
+        let expected = "\
+(define
+ (f x)
+ (g
+  x
+  [1 2 3]
+  {:key (long list of items)
+   :other 2}))";
+        assert_eq!(pretty(&val, 30), expected);
+
+        // whatever the width, it reads back as the same value
+        for width in [0, 10, 20, 40] {
+            assert_eq!(read(&pretty(&val, width)), val, "width {}", width);
+        }
+
+        // atoms are never broken, and a lone item stays next to its brackets
+        assert_eq!(pretty(&read(r#""a long string""#), 4), r#""a long string""#);
+        assert_eq!(pretty(&read("((a b c))"), 4), "((a\n  b\n  c))");
+    }
+}
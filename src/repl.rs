@@ -0,0 +1,98 @@
+use std::env;
+use std::path::PathBuf;
+
+use crate::ast::read_program;
+use crate::line_editor::{Line, LineEditor};
+use crate::printer::pretty;
+use crate::span::Source;
+use crate::{Error, ErrorKind, Result, System};
+
+const PROMPT: &str = "alisp> ";
+const CONTINUATION_PROMPT: &str = "  ...> ";
+// results wider than this are broken across lines
+const WIDTH: usize = 80;
+
+fn history_path() -> Option<PathBuf> {
+    match env::var_os("ALISP_HISTORY") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => env::var_os("HOME").map(|home| PathBuf::from(home).join(".alisp_history")),
+    }
+}
+
+// whether more lines are needed before `src` can be read, i.e. it has unclosed parens or strings
+pub fn is_incomplete(src: &str) -> bool {
+    match read_program(&Source::new("<repl>", src)) {
+        Ok(_) => false,
+        Err(err) => matches!(err.kind(), ErrorKind::ParseError { incomplete: true, .. }),
+    }
+}
+
+// prints the value of every form in the input
+fn eval_input(sys: &mut System, src: &str) -> Result<()> {
+    for expr in read_program(&Source::new("<repl>", src))? {
+        println!("{}", pretty(&sys.eval(&expr)?, WIDTH));
+    }
+    Ok(())
+}
+
+pub fn run(sys: &mut System) -> Result<()> {
+    let mut editor = LineEditor::new(history_path());
+    let mut input = String::new();
+
+    loop {
+        let prompt = if input.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+        match editor.read_line(prompt).map_err(|err| Error::io("cannot read input", &err))? {
+            Line::Text(line) => {
+                input.push_str(&line);
+                input.push('\n');
+            }
+            Line::Interrupted => {
+                input.clear();
+                continue;
+            }
+            Line::Eof => break,
+        }
+
+        if is_incomplete(&input) {
+            continue;
+        }
+        let src = std::mem::take(&mut input);
+        if src.trim().is_empty() {
+            continue;
+        }
+
+        if let Err(err) = editor.add_history(&src) {
+            eprintln!("{}", Error::io("cannot save history", &err).render());
+        }
+        if let Err(err) = eval_input(sys, &src) {
+            eprintln!("{}", err.render());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_incomplete() {
+        let input = [
+            ("(+ 1", true),
+            ("(+ 1\n  (* 2 3)", true),
+            ("(f \"a (", true),
+            ("(f #| a", true),
+            ("(f ; )\n", true),
+            ("{:a #{1", true),
+            ("[1 [2]", true),
+            ("(+ 1 2)", false),
+            ("(+ 1 2))", false),
+            ("1.2.3", false),
+            ("", false),
+        ];
+        for (src, expected) in input {
+            assert_eq!(is_incomplete(src), expected, "`{}`", src);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+impl Source {
+    pub fn new(name: &str, text: &str) -> Arc<Self> {
+        Arc::new(Self { name: String::from(name), text: String::from(text) })
+    }
+}
+
+// `line` and `column` are 1-based and count chars, `start..end` is a byte range into the source text.
+// Spans of code that wasn't read from a source (e.g. built from Rust) have no source.
+#[derive(Clone, Default)]
+pub struct Span {
+    pub source: Option<Arc<Source>>,
+    pub line: usize,
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
+}
+impl Span {
+    // an empty span just past the end of `source`
+    pub fn end_of(source: &Arc<Source>) -> Span {
+        let last_line = source.text.rsplit('\n').next().unwrap_or("");
+        Span {
+            source: Some(source.clone()),
+            line: source.text.matches('\n').count() + 1,
+            column: last_line.chars().count() + 1,
+            start: source.text.len(),
+            end: source.text.len(),
+        }
+    }
+
+    pub fn to(&self, other: &Span) -> Span {
+        Span { end: other.end, ..self.clone() }
+    }
+}
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.source {
+            Some(source) => write!(f, "{}:{}:{}", source.name, self.line, self.column),
+            None => write!(f, "<unknown>"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+}
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: &Span) -> Self {
+        Self { message: message.into(), span: span.clone() }
+    }
+
+    // the message followed by the offending source line with the span underlined
+    pub fn render(&self) -> String {
+        let Some(source) = &self.span.source else {
+            return format!("error: {}", self.message);
+        };
+
+        let line_start = source.text[..self.span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source.text[line_start..]
+            .find('\n')
+            .map_or(source.text.len(), |i| line_start + i);
+        let line = &source.text[line_start..line_end];
+        let underlined = &source.text[self.span.start..self.span.end.clamp(self.span.start, line_end)];
+
+        let number = self.span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        format!(
+            "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+            self.message,
+            gutter, source.name, self.span.line, self.span.column,
+            gutter,
+            number, line,
+            gutter, " ".repeat(self.span.column - 1), "^".repeat(underlined.chars().count().max(1)),
+        )
+    }
+}
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let source = Source::new("test.lisp", "(f 1)\n(g (h \"é\" 2) 3)\n");
+        let span = Span { source: Some(source), line: 2, column: 4, start: 9, end: 19 };
+        let rendered = Diagnostic::new("something went wrong", &span).render();
+        let expected = [
+            "error: something went wrong",
+            " --> test.lisp:2:4",
+            "  |",
+            "2 | (g (h \"é\" 2) 3)",
+            "  |    ^^^^^^^^^",
+        ];
+        assert_eq!(rendered, expected.join("\n"));
+
+        let rendered = Diagnostic::new("no location", &Span::default()).render();
+        assert_eq!(rendered, "error: no location");
+    }
+}
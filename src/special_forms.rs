@@ -0,0 +1,597 @@
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use crate::ast::{quote as quote_expr, to_expr, Expr, ExprKind, Val};
+use crate::cons_list::ConsList;
+use crate::error::{Error, Result};
+use crate::macros::Macro;
+use crate::symbol::Symbol;
+use crate::syntax_rules::SyntaxRules;
+use crate::{Function, Step, System};
+
+// forms return a `Step` so that the expressions in their tail positions are evaluated as tail calls
+pub type SpecialForm = fn(&mut System, &[Expr]) -> Result<Step>;
+
+// special forms get their arguments unevaluated, and take precedence over functions of the same name
+pub fn lookup(name: &str) -> Option<SpecialForm> {
+    let form: SpecialForm = match name {
+        "quote" => quote,
+        "quasiquote" => quasiquote,
+        "define" => define,
+        "defmacro" => defmacro,
+        "define-syntax" => define_syntax,
+        "lambda" | "fn" => lambda,
+        "set!" => set,
+        "let" => let_,
+        "let*" => let_star,
+        "letrec" => letrec,
+        "if" => if_,
+        "cond" => cond,
+        "when" => when,
+        "unless" => unless,
+        "begin" => begin,
+        "and" => and,
+        "or" => or,
+        _ => return None,
+    };
+    Some(form)
+}
+
+fn expect_args(name: &str, args: &[Expr], n: usize) -> Result<()> {
+    if args.len() == n {
+        Ok(())
+    } else {
+        let plural = if n == 1 { "" } else { "s" };
+        Err(Error::syntax(format!("`{}` expects {} argument{}, got {}", name, n, plural, args.len())))
+    }
+}
+
+fn expect_sym<'a>(name: &str, expr: &'a Expr) -> Result<&'a str> {
+    match &expr.kind {
+        ExprKind::Sym(sym) => Ok(sym),
+        _ => Err(Error::syntax(format!("`{}` expects a name, got `{}`", name, expr))),
+    }
+}
+
+// special forms are looked up before macros, so a macro named after one could never be used
+fn expect_macro_name<'a>(name: &str, expr: &'a Expr) -> Result<&'a str> {
+    let sym = expect_sym(name, expr)?;
+    match lookup(sym) {
+        Some(_) => Err(Error::syntax(format!("`{}` can't redefine the special form `{}`", name, sym))),
+        None => Ok(sym),
+    }
+}
+
+// splits a parenthesised form like `(test body...)` into its head and the rest
+fn split_form(expr: &Expr) -> Option<(Expr, &[Expr])> {
+    match &expr.kind {
+        ExprKind::Func(name, args) => {
+            let head = Expr { kind: ExprKind::Sym(name.clone()), span: expr.span.clone() };
+            Some((head, args))
+        }
+        ExprKind::List(items) => items.split_first().map(|(head, rest)| (head.clone(), rest)),
+        _ => None,
+    }
+}
+
+// the last form of a body is in tail position
+pub fn eval_body(sys: &mut System, body: &[Expr]) -> Result<Step> {
+    let Some((last, init)) = body.split_last() else {
+        return Ok(Step::Done(Val::Nil));
+    };
+    for expr in init {
+        sys.eval(expr)?;
+    }
+    sys.eval_step(last)
+}
+
+fn quote(_: &mut System, args: &[Expr]) -> Result<Step> {
+    expect_args("quote", args, 1)?;
+    Ok(Step::Done(quote_expr(&args[0])))
+}
+
+// `` `(a ,b ,@c) `` builds a list from the template, evaluating what's unquoted
+fn quasiquote(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    expect_args("quasiquote", args, 1)?;
+    Ok(Step::Done(template(sys, &args[0], 1)?))
+}
+
+// `depth` counts the quasiquotes around `expr` that haven't been unquoted, only depth 1 is evaluated
+fn template(sys: &mut System, expr: &Expr, depth: usize) -> Result<Val> {
+    let nested = |name: &str, val| Val::List(ConsList::from(vec![Val::Sym(Symbol::new(name)), val]));
+    match &expr.kind {
+        ExprKind::Func(name, args) if name == "unquote" && args.len() == 1 && depth == 1 => sys.eval(&args[0]),
+        // a splice is an unquote too, it only splices once it's at depth 1
+        ExprKind::Func(name, args) if matches!(name.as_str(), "unquote" | "unquote-splicing") && args.len() == 1 && depth > 1 => {
+            Ok(nested(name, template(sys, &args[0], depth - 1)?))
+        }
+        ExprKind::Func(name, args) if name == "quasiquote" && args.len() == 1 => {
+            Ok(nested(name, template(sys, &args[0], depth + 1)?))
+        }
+        ExprKind::Func(name, _) if name == "unquote-splicing" && depth == 1 => {
+            Err(Error::syntax("`unquote-splicing` is only valid inside a list"))
+        }
+        ExprKind::Func(name, args) => {
+            let mut items = vec![Val::Sym(Symbol::new(name))];
+            template_items(sys, args, depth, &mut items)?;
+            Ok(Val::List(items.into()))
+        }
+        ExprKind::List(items) => {
+            let mut vals = vec![];
+            template_items(sys, items, depth, &mut vals)?;
+            Ok(Val::List(vals.into()))
+        }
+        ExprKind::Vector(items) => {
+            let mut vals = vec![];
+            template_items(sys, items, depth, &mut vals)?;
+            Ok(Val::Vector(vals))
+        }
+        ExprKind::Map(entries) => {
+            let mut map = BTreeMap::new();
+            for (k, v) in entries {
+                map.insert(template(sys, k, depth)?, template(sys, v, depth)?);
+            }
+            Ok(Val::Map(map))
+        }
+        ExprKind::Set(items) => {
+            let mut vals = vec![];
+            template_items(sys, items, depth, &mut vals)?;
+            Ok(Val::Set(vals.into_iter().collect()))
+        }
+        // the reader has already turned `'x` into data, but there may be unquotes inside it
+        ExprKind::Atom(val @ (Val::List(_) | Val::Vector(_) | Val::Map(_) | Val::Set(_) | Val::Sym(_))) => {
+            Ok(nested("quote", template(sys, &to_expr(val, &expr.span), depth)?))
+        }
+        _ => Ok(quote_expr(expr)),
+    }
+}
+
+fn template_items(sys: &mut System, exprs: &[Expr], depth: usize, items: &mut Vec<Val>) -> Result<()> {
+    for expr in exprs {
+        match &expr.kind {
+            ExprKind::Func(name, args) if name == "unquote-splicing" && args.len() == 1 && depth == 1 => {
+                match sys.eval(&args[0])? {
+                    Val::List(spliced) => items.extend(spliced.iter().cloned()),
+                    Val::Vector(spliced) => items.extend(spliced),
+                    val => {
+                        let message = format!("`unquote-splicing` expects a list, got `{}`", val);
+                        return Err(Error::syntax(message));
+                    }
+                }
+            }
+            _ => items.push(template(sys, expr, depth)?),
+        }
+    }
+    Ok(())
+}
+
+// `(x y)`, `(x &rest more)`, or a single name that collects all the arguments into a list
+fn params(name: &str, expr: &Expr) -> Result<(Vec<String>, Option<String>)> {
+    let names = match &expr.kind {
+        ExprKind::Sym(rest) => return Ok((vec![], Some(rest.clone()))),
+        ExprKind::Func(first, rest) => {
+            let mut names = vec![first.as_str()];
+            for param in rest {
+                names.push(expect_sym(name, param)?);
+            }
+            names
+        }
+        ExprKind::List(items) if items.is_empty() => vec![],
+        _ => return Err(Error::syntax(format!("`{}` expects a list of parameter names", name))),
+    };
+    rest_param(name, names)
+}
+
+// splits off the name after `&rest`, if there is one
+fn rest_param(name: &str, names: Vec<&str>) -> Result<(Vec<String>, Option<String>)> {
+    match names.iter().position(|param| *param == "&rest") {
+        None => Ok((names.iter().map(|param| param.to_string()).collect(), None)),
+        Some(i) if i + 2 == names.len() => {
+            Ok((names[..i].iter().map(|param| param.to_string()).collect(), Some(names[i + 1].to_string())))
+        }
+        Some(_) => Err(Error::syntax(format!("`{}` expects a single name after `&rest`", name))),
+    }
+}
+
+fn make_lambda(
+    sys: &System,
+    name: Option<String>,
+    (params, rest): (Vec<String>, Option<String>),
+    body: &[Expr],
+) -> Rc<Function> {
+    Rc::new(Function::Lambda {
+        name,
+        params,
+        rest,
+        body: body.to_vec(),
+        env: sys.capture(),
+    })
+}
+
+// `(lambda (x y) body...)`
+fn lambda(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    match args.split_first() {
+        Some((params_expr, body)) if !body.is_empty() => {
+            Ok(Step::Done(Val::Func(make_lambda(sys, None, params("lambda", params_expr)?, body))))
+        }
+        _ => Err(Error::syntax("`lambda` expects parameters and a body")),
+    }
+}
+
+// `(define name value)`, or `(define (name params...) body...)` for functions
+fn define(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    let (name, val) = match args.split_first() {
+        Some((Expr { kind: ExprKind::Func(name, params_exprs), .. }, body)) if !body.is_empty() => {
+            let mut names = vec![];
+            for param in params_exprs {
+                names.push(expect_sym("define", param)?);
+            }
+            let params = rest_param("define", names)?;
+            (name.as_str(), Val::Func(make_lambda(sys, Some(name.clone()), params, body)))
+        }
+        _ => {
+            expect_args("define", args, 2)?;
+            (expect_sym("define", &args[0])?, sys.eval(&args[1])?)
+        }
+    };
+    sys.define(name.to_string(), val.clone());
+    Ok(Step::Done(val))
+}
+
+// `(defmacro name (params...) body...)`, a function from code to code that's called with its
+// arguments unevaluated and whose result is evaluated in place of the call
+fn defmacro(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    let (name, params_expr, body) = match args {
+        [name, params_expr, body @ ..] if !body.is_empty() => (expect_macro_name("defmacro", name)?, params_expr, body),
+        _ => return Err(Error::syntax("`defmacro` expects a name, parameters and a body")),
+    };
+    let func = make_lambda(sys, Some(name.to_string()), params("defmacro", params_expr)?, body);
+    sys.redefine_macro(name.to_string(), Macro::Procedural(func));
+    Ok(Step::Done(Val::Sym(Symbol::new(name))))
+}
+
+// `(define-syntax name (syntax-rules (literals...) (pattern template)...))`
+fn define_syntax(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    expect_args("define-syntax", args, 2)?;
+    let name = expect_macro_name("define-syntax", &args[0])?;
+    let rules = SyntaxRules::new(name, &quote_expr(&args[1]), sys.capture())?;
+    sys.redefine_macro(name.to_string(), Macro::Rules(Rc::new(rules)));
+    Ok(Step::Done(Val::Sym(Symbol::new(name))))
+}
+
+fn set(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    expect_args("set!", args, 2)?;
+    let name = expect_sym("set!", &args[0])?;
+    let val = sys.eval(&args[1])?;
+    sys.assign(name.to_string(), val.clone())?;
+    Ok(Step::Done(val))
+}
+
+// `((name init) ...)`
+fn bindings<'a>(name: &str, expr: &'a Expr) -> Result<Vec<(&'a str, &'a Expr)>> {
+    let malformed = || Error::syntax(format!("`{}` expects a list of `(name value)` bindings", name));
+    match &expr.kind {
+        ExprKind::List(items) => items
+            .iter()
+            .map(|binding| match &binding.kind {
+                ExprKind::Func(name, init) if init.len() == 1 => Ok((name.as_str(), &init[0])),
+                _ => Err(malformed()),
+            })
+            .collect(),
+        _ => Err(malformed()),
+    }
+}
+
+fn let_(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    let Some((bindings_expr, body)) = args.split_first() else {
+        return Err(Error::syntax("`let` expects bindings and a body"));
+    };
+
+    // the values are evaluated before any of the names are bound
+    let mut scope = HashMap::new();
+    for (name, init) in bindings("let", bindings_expr)? {
+        scope.insert(name.to_string(), sys.eval(init)?);
+    }
+    sys.in_scope(scope, |sys| eval_body(sys, body))
+}
+
+fn let_star(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    let Some((bindings_expr, body)) = args.split_first() else {
+        return Err(Error::syntax("`let*` expects bindings and a body"));
+    };
+    let bindings = bindings("let*", bindings_expr)?;
+    if bindings.is_empty() {
+        return sys.in_scope(HashMap::new(), |sys| eval_body(sys, body));
+    }
+    let_nested(sys, &bindings, body)
+}
+
+// each binding gets a scope of its own that the rest are evaluated in, so a closure among the
+// values keeps seeing the bindings before it even if a later one reuses the name
+fn let_nested(sys: &mut System, bindings: &[(&str, &Expr)], body: &[Expr]) -> Result<Step> {
+    let Some(((name, init), rest)) = bindings.split_first() else {
+        return eval_body(sys, body);
+    };
+    let val = sys.eval(init)?;
+    sys.in_scope(HashMap::from([(name.to_string(), val)]), |sys| let_nested(sys, rest, body))
+}
+
+// every name is bound, to nil, before any value is evaluated, so the lambdas among the values can
+// call each other. The values are then evaluated and assigned in order.
+fn letrec(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    let Some((bindings_expr, body)) = args.split_first() else {
+        return Err(Error::syntax("`letrec` expects bindings and a body"));
+    };
+    let bindings = bindings("letrec", bindings_expr)?;
+
+    let scope = bindings.iter().map(|(name, _)| (name.to_string(), Val::Nil)).collect();
+    sys.in_scope(scope, |sys| {
+        for (name, init) in bindings {
+            let val = sys.eval(init)?;
+            sys.define(name.to_string(), val);
+        }
+        eval_body(sys, body)
+    })
+}
+
+fn if_(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    if args.len() != 2 && args.len() != 3 {
+        return Err(Error::syntax(format!("`if` expects 2 or 3 arguments, got {}", args.len())));
+    }
+    if sys.eval(&args[0])?.is_truthy() {
+        sys.eval_step(&args[1])
+    } else {
+        args.get(2).map_or(Ok(Step::Done(Val::Nil)), |alt| sys.eval_step(alt))
+    }
+}
+
+// `(cond (test body...) ... (else body...))`, a clause without a body returns the value of its test
+fn cond(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    for clause in args {
+        let Some((test, body)) = split_form(clause) else {
+            return Err(Error::syntax("`cond` expects clauses of the form `(test body...)`"));
+        };
+
+        let is_else = matches!(&test.kind, ExprKind::Sym(name) if name == "else");
+        let val = if is_else { Val::Bool(true) } else { sys.eval(&test)? };
+        if val.is_truthy() {
+            return if body.is_empty() { Ok(Step::Done(val)) } else { eval_body(sys, body) };
+        }
+    }
+    Ok(Step::Done(Val::Nil))
+}
+
+fn when(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    let Some((test, body)) = args.split_first() else {
+        return Err(Error::syntax("`when` expects a test and a body"));
+    };
+    if sys.eval(test)?.is_truthy() {
+        eval_body(sys, body)
+    } else {
+        Ok(Step::Done(Val::Nil))
+    }
+}
+
+fn unless(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    let Some((test, body)) = args.split_first() else {
+        return Err(Error::syntax("`unless` expects a test and a body"));
+    };
+    if sys.eval(test)?.is_truthy() {
+        Ok(Step::Done(Val::Nil))
+    } else {
+        eval_body(sys, body)
+    }
+}
+
+fn begin(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    eval_body(sys, args)
+}
+
+// returns the first falsy value, or the last one, which is in tail position
+fn and(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    let Some((last, init)) = args.split_last() else {
+        return Ok(Step::Done(Val::Bool(true)));
+    };
+    for arg in init {
+        let val = sys.eval(arg)?;
+        if !val.is_truthy() {
+            return Ok(Step::Done(val));
+        }
+    }
+    sys.eval_step(last)
+}
+
+// returns the first truthy value, or the last one, which is in tail position
+fn or(sys: &mut System, args: &[Expr]) -> Result<Step> {
+    let Some((last, init)) = args.split_last() else {
+        return Ok(Step::Done(Val::Bool(false)));
+    };
+    for arg in init {
+        let val = sys.eval(arg)?;
+        if val.is_truthy() {
+            return Ok(Step::Done(val));
+        }
+    }
+    sys.eval_step(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_program;
+    use Val::*;
+
+    fn eval(src: &str) -> Result<Val> {
+        System::new().eval_program(&parse_program(src)?)
+    }
+
+    #[test]
+    fn test_define_and_set() {
+        assert_eq!(eval("(define x 1) (define y (+ x 1)) (set! x 10) (+ x y)").unwrap(), Int(12));
+        assert_eq!(eval("(define x 1) (define x 2) x").unwrap(), Int(2));
+        assert_eq!(eval("(define (f) 1) (define (g) (f)) (define (f) 2) (g)").unwrap(), Int(2));
+        assert!(eval("(set! x 1)").is_err());
+        assert!(eval("(define 1 2)").is_err());
+    }
+
+    #[test]
+    fn test_let() {
+        let input = [
+            ("(define x 1) (let ((x 2) (y x)) (+ x y))", Int(3)),
+            ("(define x 1) (let* ((x 2) (y x)) (+ x y))", Int(4)),
+            ("(let* ((x 1) (f (lambda () x)) (x 2)) (f))", Int(1)),
+            ("(let* ((x 1) (x (+ x 1))) x)", Int(2)),
+            ("(letrec ((a 1) (b (+ a 1))) (* a b))", Int(2)),
+            (
+                "(letrec ((even? (lambda (n) (if (= n 0) 1 (odd? (- n 1)))))
+                          (odd? (lambda (n) (if (= n 0) 0 (even? (- n 1)))))
+                          (r (odd? 7)))
+                   r)",
+                Int(1),
+            ),
+            // the names are bound before any value is evaluated, shadowing the globals
+            ("(define x 10) (letrec ((y x) (x 1)) y)", Nil),
+            ("(define x 10) (letrec ((get (lambda () x)) (x 1)) (get))", Int(1)),
+            ("(define x 1) (let ((x 2)) (set! x 3)) x", Int(1)),
+            ("(define x 1) (let ((y 2)) (set! x 3)) x", Int(3)),
+            ("(let ((x 1)) (let ((y 2)) (set! x 5)) x)", Int(5)),
+            ("(let () 1 2)", Int(2)),
+        ];
+        for (src, expected) in input {
+            assert_eq!(eval(src).unwrap(), expected, "`{}`", src);
+        }
+        // let bindings don't outlive the body
+        assert!(eval("(let ((z 1)) z) z").is_err());
+        assert!(eval("(let* () (define z 1)) z").is_err());
+        assert!(eval("(let ((x 1) y) x)").is_err());
+    }
+
+    #[test]
+    fn test_lambda() {
+        let input = [
+            ("((lambda (x y) (+ x y)) 1 2)", Int(3)),
+            ("((fn () 1 2))", Int(2)),
+            ("((lambda args args) 1 2)", List(vec![Int(1), Int(2)].into())),
+            ("((lambda (x &rest more) more) 1 2 3)", List(vec![Int(2), Int(3)].into())),
+            ("((lambda (&rest more) more))", List(vec![].into())),
+            ("(define (square x) (* x x)) (square 5)", Int(25)),
+            ("(define (f x &rest r) r) (f 1 2 3)", List(vec![Int(2), Int(3)].into())),
+            ("(define (f x &rest r) r) (f 1)", List(vec![].into())),
+            ("(define (fact n) (if (< n 2) 1 (* n (fact (- n 1))))) (fact 10)", Int(3628800)),
+            ("(define (twice f x) (f (f x))) (twice (lambda (x) (* x 3)) 2)", Int(18)),
+            // closures capture their defining scope
+            ("(define (adder n) (lambda (x) (+ x n))) ((adder 10) 5)", Int(15)),
+            ("(define add5 (let ((n 5)) (lambda (x) (+ x n)))) (add5 1)", Int(6)),
+            // ... by reference, so changes are shared
+            (
+                "(define counter (let ((n 0)) (lambda () (set! n (+ n 1)) n))) (counter) (counter)",
+                Int(2),
+            ),
+            ("(letrec ((even (lambda (n) (if (= n 0) 1 (odd (- n 1))))) (odd (lambda (n) (if (= n 0) 0 (even (- n 1)))))) (even 10))", Int(1)),
+            ("(define (f) (define (g n) (if (= n 0) 0 (g (- n 1)))) (g 3)) (f)", Int(0)),
+            // free names are looked up where the lambda was defined, not where it's called
+            ("(define x 1) (define (get-x) x) (let ((x 2)) (get-x))", Int(1)),
+        ];
+        for (src, expected) in input {
+            assert_eq!(eval(src).unwrap(), expected, "`{}`", src);
+        }
+
+        let input = [
+            "((lambda (x) x))", "((lambda (x) x) 1 2)", "(lambda (x))", "(lambda (1) x)", "(1 2)",
+            "(lambda (x &rest) x)", "(lambda (&rest x y) x)", "(define (f &rest) 1)",
+        ];
+        for src in input {
+            assert!(eval(src).is_err(), "`{}` should fail", src);
+        }
+        assert_eq!(
+            eval("(define (f x) x) (f)").unwrap_err().to_string(),
+            "function `f` expects 1 argument, got 0"
+        );
+    }
+
+    #[test]
+    fn test_quasiquote() {
+        let sym = |name: &str| Sym(Symbol::new(name));
+        let input = [
+            ("'x", sym("x")),
+            ("`(1 ,(+ 1 1) ,@(map (lambda (x) (* x 3)) '(1 2)))", List(vec![Int(1), Int(2), Int(3), Int(6)].into())),
+            ("(define xs '(1 2)) `(f ,@xs x)", List(vec![sym("f"), Int(1), Int(2), sym("x")].into())),
+            ("`(() ,@'())", List(vec![List(vec![].into())].into())),
+            ("`[1 ,(+ 1 1) ,@'(3 4)]", Vector(vec![Int(1), Int(2), Int(3), Int(4)])),
+            ("`(f ,@[1 2])", List(vec![sym("f"), Int(1), Int(2)].into())),
+            ("(define x 5) `{:a ,x :b x}", Map([(Keyword(Symbol::new("a")), Int(5)), (Keyword(Symbol::new("b")), sym("x"))].into())),
+            ("`#{1 ,(+ 1 1) ,@'(3)}", Set([Int(1), Int(2), Int(3)].into())),
+            ("`'{:a ,(+ 1 2)}", List(vec![sym("quote"), Map([(Keyword(Symbol::new("a")), Int(3))].into())].into())),
+            // only the innermost quasiquote's unquotes are evaluated
+            (
+                "`(a `(b ,(c ,(+ 1 2))))",
+                List(vec![
+                    sym("a"),
+                    List(vec![
+                        sym("quasiquote"),
+                        List(vec![sym("b"), List(vec![sym("unquote"), List(vec![sym("c"), Int(3)].into())].into())].into()),
+                    ].into()),
+                ].into()),
+            ),
+            // ... including those inside a nested splice
+            (
+                "`(a `(b ,@(c ,(+ 1 2))))",
+                List(vec![
+                    sym("a"),
+                    List(vec![
+                        sym("quasiquote"),
+                        List(vec![sym("b"), List(vec![sym("unquote-splicing"), List(vec![sym("c"), Int(3)].into())].into())].into()),
+                    ].into()),
+                ].into()),
+            ),
+        ];
+        for (src, expected) in input {
+            assert_eq!(eval(src).unwrap(), expected, "`{}`", src);
+        }
+
+        for src in ["`,@'(1)", "`(1 ,@2)", "(quasiquote)"] {
+            assert!(eval(src).is_err(), "`{}` should fail", src);
+        }
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let input = [
+            ("(if (< 1 2) 1 2)", Int(1)),
+            ("(if (> 1 2) 1 2)", Int(2)),
+            ("(if (> 1 2) 1)", Nil),
+            ("(if 0 1 2)", Int(1)),
+            ("(if nil 1 2)", Int(2)),
+            ("(if #f 1 2)", Int(2)),
+            ("(if false 1 2)", Int(2)),
+            ("(if '() 1 2)", Int(1)),
+            (r#"(if "" 1 2)"#, Int(1)),
+            ("(cond (nil 1) (#f 2) (#t 3))", Int(3)),
+            ("(when nil 1)", Nil),
+            ("(unless #f 1)", Int(1)),
+            ("(and 1 nil 2)", Nil),
+            ("(or #f nil)", Nil),
+            ("(or nil 0)", Int(0)),
+            ("(define x 5) (cond ((< x 3) 1) ((< x 6) 2) (else 3))", Int(2)),
+            ("(cond ((> 1 2) 1) (else 2 3))", Int(3)),
+            ("(cond ((> 1 2) 1))", Nil),
+            ("(define x 7) (cond (x))", Int(7)),
+            ("(when (< 1 2) 1 2)", Int(2)),
+            ("(unless (< 1 2) 1 2)", Nil),
+            ("(begin 1 2 3)", Int(3)),
+            ("(and 1 2)", Int(2)),
+            ("(and)", Bool(true)),
+            ("(or (> 1 2) 3)", Int(3)),
+            ("(or)", Bool(false)),
+        ];
+        for (src, expected) in input {
+            assert_eq!(eval(src).unwrap(), expected, "`{}`", src);
+        }
+
+        // the branches that aren't taken are never evaluated
+        assert_eq!(eval("(if (< 1 2) 1 (undefined))").unwrap(), Int(1));
+        assert_eq!(eval("(and (> 1 2) (undefined))").unwrap(), Bool(false));
+        assert_eq!(eval("(or 1 (undefined))").unwrap(), Int(1));
+        assert!(eval("(if 1)").is_err());
+    }
+}
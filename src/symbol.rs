@@ -0,0 +1,104 @@
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+thread_local! {
+    static INTERNED: RefCell<HashSet<Rc<str>>> = RefCell::new(HashSet::new());
+    static GENSYMS: Cell<usize> = const { Cell::new(0) };
+}
+
+// an interned name: every symbol with the same name shares one allocation, so comparing two
+// symbols only compares pointers
+#[derive(Clone)]
+pub struct Symbol(Rc<str>);
+impl Symbol {
+    pub fn new(name: &str) -> Self {
+        INTERNED.with(|interned| {
+            let mut interned = interned.borrow_mut();
+            match interned.get(name) {
+                Some(name) => Symbol(name.clone()),
+                None => {
+                    let name: Rc<str> = Rc::from(name);
+                    interned.insert(name.clone());
+                    Symbol(name)
+                }
+            }
+        })
+    }
+
+    // a symbol that no other symbol is equal to: it isn't interned, so not even one made from its
+    // printed name with `string->symbol` shares its allocation
+    pub fn gensym(prefix: &str) -> Self {
+        let n = GENSYMS.with(|count| {
+            count.set(count.get() + 1);
+            count.get()
+        });
+        Symbol(Rc::from(format!("{}#{}", prefix, n)))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl Eq for Symbol {}
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self.0.as_ptr(), state)
+    }
+}
+// by name, then by allocation so that a gensym and a symbol with the same name still differ
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if Rc::ptr_eq(&self.0, &other.0) {
+            return Ordering::Equal;
+        }
+        self.name().cmp(other.name()).then_with(|| self.0.as_ptr().cmp(&other.0.as_ptr()))
+    }
+}
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interning() {
+        let a = Symbol::new("abc");
+        let b = Symbol::new(&(String::from("ab") + "c"));
+        assert!(Rc::ptr_eq(&a.0, &b.0));
+        assert_eq!(a, b);
+        assert_ne!(a, Symbol::new("abd"));
+        assert!(a == *"abc");
+
+        let (g1, g2) = (Symbol::gensym("g"), Symbol::gensym("g"));
+        assert_ne!(g1, g2);
+        assert!(g1.name().starts_with("g#"));
+
+        let named = Symbol::new(g1.name());
+        assert_ne!(g1, named);
+        assert_ne!(g1.cmp(&named), Ordering::Equal);
+        assert_eq!(g1.cmp(&g1.clone()), Ordering::Equal);
+    }
+}
@@ -0,0 +1,442 @@
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use crate::ast::Val;
+use crate::env::Env;
+use crate::error::{Error, Result};
+use crate::symbol::Symbol;
+use crate::{special_forms, System};
+
+// names the special forms look for, which a template has to be able to produce as they are
+const AUXILIARY: [&str; 5] = ["else", "&rest", "quasiquote", "unquote", "unquote-splicing"];
+
+const ELLIPSIS: &str = "...";
+
+// what a pattern variable matched, nested once for every `...` that follows it in the pattern
+#[derive(Clone)]
+enum Binding {
+    One(Val),
+    Many(Vec<Binding>),
+}
+
+// whether the part of a template being filled in is code, whose names are renamed, or quoted data
+#[derive(Clone, Copy)]
+enum Quoting {
+    Code,
+    Quote,
+    // the number of quasiquotes around it that haven't been unquoted
+    Quasi(usize),
+}
+impl Quoting {
+    // inside a list headed by `head`
+    fn enter(self, head: Option<&Val>) -> Quoting {
+        let Some(Val::Sym(head)) = head else {
+            return self;
+        };
+        match (self, head.name()) {
+            (Quoting::Code, "quote") => Quoting::Quote,
+            (Quoting::Code, "quasiquote") => Quoting::Quasi(1),
+            (Quoting::Quasi(depth), "quasiquote") => Quoting::Quasi(depth + 1),
+            (Quoting::Quasi(1), "unquote" | "unquote-splicing") => Quoting::Code,
+            (Quoting::Quasi(depth), "unquote" | "unquote-splicing") => Quoting::Quasi(depth - 1),
+            _ => self,
+        }
+    }
+}
+
+// `(syntax-rules (literals...) (pattern template)...)`
+pub struct SyntaxRules {
+    name: String,
+    literals: Vec<Symbol>,
+    rules: Vec<(Vec<Val>, Val)>,
+    // where the macro was defined, which is where the names its templates introduce are looked up
+    env: Rc<Env>,
+}
+impl SyntaxRules {
+    pub fn new(name: &str, spec: &Val, env: Rc<Env>) -> Result<Self> {
+        let malformed = || {
+            Error::syntax(format!(
+                "`{}` expects `(syntax-rules (literals...) (pattern template)...)`",
+                name
+            ))
+        };
+        let Val::List(items) = spec else {
+            return Err(malformed());
+        };
+        let items = items.to_vec();
+        let [Val::Sym(head), Val::List(literals), rules @ ..] = items.as_slice() else {
+            return Err(malformed());
+        };
+        if *head != *"syntax-rules" {
+            return Err(malformed());
+        }
+
+        let literals = literals
+            .iter()
+            .map(|literal| match literal {
+                Val::Sym(literal) => Ok(literal.clone()),
+                _ => Err(malformed()),
+            })
+            .collect::<Result<_>>()?;
+        // the keyword in the head of each pattern is ignored
+        let rules = rules
+            .iter()
+            .map(|rule| match rule {
+                Val::List(rule) => match rule.to_vec().as_slice() {
+                    [Val::List(pattern), template] if !pattern.is_empty() => {
+                        Ok((pattern.iter().skip(1).cloned().collect(), template.clone()))
+                    }
+                    _ => Err(malformed()),
+                },
+                _ => Err(malformed()),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { name: name.to_string(), literals, rules, env })
+    }
+
+    pub fn scope(&self) -> &Rc<Env> {
+        &self.env
+    }
+
+    // rewrites the arguments of a call with the first rule whose pattern matches them
+    pub fn expand(&self, sys: &mut System, args: &[Val]) -> Result<Val> {
+        for (pattern, template) in &self.rules {
+            let mut binds = HashMap::new();
+            if self.match_list(pattern, args, &mut binds) {
+                let renaming = (sys.macros.add_scope(&self.env), sys.macros.next_expansion());
+                return self.instantiate(sys, template, &binds, renaming, Quoting::Code);
+            }
+        }
+        Err(Error::syntax(format!("no `syntax-rules` pattern of `{}` matches its arguments", self.name)))
+    }
+
+    fn match_pattern(&self, pattern: &Val, input: &Val, binds: &mut HashMap<Symbol, Binding>) -> bool {
+        match pattern {
+            Val::Sym(name) if *name == *"_" => true,
+            Val::Sym(name) if self.literals.contains(name) => input == pattern,
+            Val::Sym(name) => {
+                binds.insert(name.clone(), Binding::One(input.clone()));
+                true
+            }
+            Val::List(patterns) => match input {
+                Val::List(inputs) => self.match_list(&patterns.to_vec(), &inputs.to_vec(), binds),
+                _ => false,
+            },
+            Val::Vector(patterns) => match input {
+                Val::Vector(inputs) => self.match_list(patterns, inputs, binds),
+                _ => false,
+            },
+            _ => input == pattern,
+        }
+    }
+
+    // a list pattern may have one element followed by `...`, which matches any number of inputs
+    fn match_list(&self, patterns: &[Val], inputs: &[Val], binds: &mut HashMap<Symbol, Binding>) -> bool {
+        let Some(i) = patterns.iter().position(is_ellipsis).filter(|i| *i > 0) else {
+            return patterns.len() == inputs.len()
+                && patterns.iter().zip(inputs).all(|(pattern, input)| self.match_pattern(pattern, input, binds));
+        };
+
+        let (before, repeated, after) = (&patterns[..i - 1], &patterns[i - 1], &patterns[i + 1..]);
+        if inputs.len() < before.len() + after.len() {
+            return false;
+        }
+        let (head, rest) = inputs.split_at(before.len());
+        let (middle, tail) = rest.split_at(rest.len() - after.len());
+        if !self.match_list(before, head, binds) || !self.match_list(after, tail, binds) {
+            return false;
+        }
+
+        let mut matches = vec![];
+        for input in middle {
+            let mut inner = HashMap::new();
+            if !self.match_pattern(repeated, input, &mut inner) {
+                return false;
+            }
+            matches.push(inner);
+        }
+        for var in self.pattern_vars(repeated) {
+            let each = matches.iter_mut().map(|inner| inner.remove(&var).unwrap()).collect();
+            binds.insert(var, Binding::Many(each));
+        }
+        true
+    }
+
+    fn pattern_vars(&self, pattern: &Val) -> Vec<Symbol> {
+        match pattern {
+            Val::Sym(name) if *name == *"_" || *name == *ELLIPSIS || self.literals.contains(name) => vec![],
+            Val::Sym(name) => vec![name.clone()],
+            Val::List(patterns) => patterns.iter().flat_map(|pattern| self.pattern_vars(pattern)).collect(),
+            Val::Vector(patterns) => patterns.iter().flat_map(|pattern| self.pattern_vars(pattern)).collect(),
+            _ => vec![],
+        }
+    }
+
+    // fills in the template, renaming the names it introduces unless they're quoted
+    fn instantiate(
+        &self,
+        sys: &System,
+        template: &Val,
+        binds: &HashMap<Symbol, Binding>,
+        renaming: (usize, usize),
+        quoting: Quoting,
+    ) -> Result<Val> {
+        match template {
+            Val::Sym(name) => match binds.get(name) {
+                Some(Binding::One(val)) => Ok(val.clone()),
+                Some(Binding::Many(_)) => Err(Error::syntax(format!(
+                    "pattern variable `{}` is used without `...` in `{}`",
+                    name.name(), self.name
+                ))),
+                None if matches!(quoting, Quoting::Code) && !is_syntax(sys, name.name()) => {
+                    Ok(Val::Sym(Symbol::new(&renamed(name.name(), renaming))))
+                }
+                None => Ok(template.clone()),
+            },
+            Val::List(items) => {
+                let quoting = quoting.enter(items.car());
+                let vals = self.instantiate_items(sys, items.iter(), binds, renaming, quoting)?;
+                Ok(Val::List(vals.into()))
+            }
+            Val::Vector(items) => Ok(Val::Vector(self.instantiate_items(sys, items.iter(), binds, renaming, quoting)?)),
+            Val::Map(map) => {
+                let mut vals = BTreeMap::new();
+                for (k, v) in map {
+                    let k = self.instantiate(sys, k, binds, renaming, quoting)?;
+                    vals.insert(k, self.instantiate(sys, v, binds, renaming, quoting)?);
+                }
+                Ok(Val::Map(vals))
+            }
+            Val::Set(items) => {
+                let vals = self.instantiate_items(sys, items.iter(), binds, renaming, quoting)?;
+                Ok(Val::Set(vals.into_iter().collect()))
+            }
+            _ => Ok(template.clone()),
+        }
+    }
+
+    fn instantiate_items<'a>(
+        &self,
+        sys: &System,
+        items: impl Iterator<Item = &'a Val>,
+        binds: &HashMap<Symbol, Binding>,
+        renaming: (usize, usize),
+        quoting: Quoting,
+    ) -> Result<Vec<Val>> {
+        let mut vals = vec![];
+        let mut items = items.peekable();
+        while let Some(item) = items.next() {
+            if items.next_if(|next| is_ellipsis(next)).is_none() {
+                vals.push(self.instantiate(sys, item, binds, renaming, quoting)?);
+                continue;
+            }
+            for binds in self.repeat(item, binds)? {
+                vals.push(self.instantiate(sys, item, &binds, renaming, quoting)?);
+            }
+        }
+        Ok(vals)
+    }
+
+    // the bindings for each repetition of a template followed by `...`
+    fn repeat(
+        &self,
+        template: &Val,
+        binds: &HashMap<Symbol, Binding>,
+    ) -> Result<Vec<HashMap<Symbol, Binding>>> {
+        let mut repeated = vec![];
+        for name in template_syms(template) {
+            if let Some(Binding::Many(each)) = binds.get(&name) {
+                repeated.push((name, each));
+            }
+        }
+
+        let Some((_, first)) = repeated.first() else {
+            return Err(Error::syntax(format!(
+                "`...` follows a template without repeated pattern variables in `{}`",
+                self.name
+            )));
+        };
+        if repeated.iter().any(|(_, each)| each.len() != first.len()) {
+            return Err(Error::syntax(format!(
+                "pattern variables under `...` matched different numbers of forms in `{}`",
+                self.name
+            )));
+        }
+
+        let iterations = (0..first.len())
+            .map(|i| {
+                let mut binds = binds.clone();
+                for (name, each) in &repeated {
+                    binds.insert(name.clone(), each[i].clone());
+                }
+                binds
+            })
+            .collect();
+        Ok(iterations)
+    }
+}
+
+fn is_ellipsis(val: &Val) -> bool {
+    matches!(val, Val::Sym(name) if *name == *ELLIPSIS)
+}
+
+fn template_syms(template: &Val) -> Vec<Symbol> {
+    match template {
+        Val::Sym(name) => vec![name.clone()],
+        Val::List(items) => items.iter().flat_map(template_syms).collect(),
+        Val::Vector(items) => items.iter().flat_map(template_syms).collect(),
+        _ => vec![],
+    }
+}
+
+// special forms and macros keep their meaning wherever they're used, so they're never renamed
+fn is_syntax(sys: &System, name: &str) -> bool {
+    AUXILIARY.contains(&name) || special_forms::lookup(name).is_some() || sys.lookup_macro(name).is_some()
+}
+
+// `#` can't appear in a symbol that was read, so renamed names never clash with the user's
+fn renamed(name: &str, (scope, expansion): (usize, usize)) -> String {
+    format!("{}#{}.{}", name, scope, expansion)
+}
+
+// the original name of a renamed one, and the scope to look it up in if it isn't bound where it's used
+pub fn original_name(name: &str) -> Option<(&str, usize)> {
+    let (original, suffix) = name.rsplit_once('#')?;
+    let (scope, _) = suffix.split_once('.')?;
+    Some((original, scope.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_program;
+    use Val::*;
+
+    fn eval(src: &str) -> Result<Val> {
+        System::new().eval_program(&parse_program(src)?)
+    }
+
+    #[test]
+    fn test_syntax_rules() {
+        let input = [
+            ("(define-syntax my-if (syntax-rules () ((_ c a b) (cond (c a) (else b))))) (my-if (< 2 1) 1 2)", Int(2)),
+            // rules are tried in order
+            (
+                "(define-syntax my-or (syntax-rules () ((_) (< 2 1)) ((_ e) e) ((_ e r ...) (let ((t e)) (if t t (my-or r ...))))))
+                 (my-or (< 2 1) (< 3 1) 7)",
+                Int(7),
+            ),
+            ("(define-syntax my-list (syntax-rules () ((_ x ...) '(x ...)))) (my-list 1 2 3)", List(vec![Int(1), Int(2), Int(3)].into())),
+            ("(define-syntax my-list (syntax-rules () ((_ x ...) '(x ...)))) (my-list)", List(vec![].into())),
+            // the contents of map and set templates are filled in and evaluated
+            (
+                "(define-syntax entry (syntax-rules () ((_ k v) {k (+ v 1)}))) (entry :a 2)",
+                Map([(Keyword(Symbol::new("a")), Int(3))].into()),
+            ),
+            ("(define-syntax one (syntax-rules () ((_ x ...) #{(+ x ...)}))) (one 1 2)", Set([Int(3)].into())),
+            ("(defmacro m () 1) (define-syntax m (syntax-rules () ((_) 2))) (m)", Int(2)),
+            // patterns after the ellipsis, and nested ellipses
+            (
+                "(define-syntax last (syntax-rules () ((_ x ... y) y))) (last 1 2 3)",
+                Int(3),
+            ),
+            (
+                "(define-syntax my-let* (syntax-rules ()
+                   ((_ () body ...) (let () body ...))
+                   ((_ ((n v) rest ...) body ...) (let ((n v)) (my-let* (rest ...) body ...)))))
+                 (my-let* ((a 1) (b (+ a 1))) (* a b))",
+                Int(2),
+            ),
+            (
+                "(define-syntax pairs (syntax-rules () ((_ (k v ...) ...) '((k ... ) (v ...) ...))))
+                 (pairs (a 1 2) (b 3))",
+                List(vec![
+                    List(vec![Sym(Symbol::new("a")), Sym(Symbol::new("b"))].into()),
+                    List(vec![Int(1), Int(2)].into()),
+                    List(vec![Int(3)].into()),
+                ].into()),
+            ),
+            // literals have to appear as they are
+            (
+                "(define-syntax for (syntax-rules (in) ((_ x in xs body) (map (lambda (x) body) xs))))
+                 (for y in '(1 2) (* y 10))",
+                List(vec![Int(10), Int(20)].into()),
+            ),
+        ];
+        for (src, expected) in input {
+            assert_eq!(eval(src).unwrap(), expected, "`{}`", src);
+        }
+
+        let input = [
+            "(define-syntax m (syntax-rules () ((_ a) a))) (m)",
+            "(define-syntax m (syntax-rules (in) ((_ in) 1))) (m out)",
+            "(define-syntax m (syntax-rules () ((_ a ...) a))) (m 1)",
+            "(define-syntax m (syntax-rules () ((_ a) (a ...)))) (m 1)",
+            "(define-syntax m (syntax-rules () (_ 1)))",
+            "(define-syntax m (lambda (x) x))",
+            "(define-syntax if (syntax-rules () ((_ a) a)))",
+        ];
+        for src in input {
+            assert!(eval(src).is_err(), "`{}` should fail", src);
+        }
+    }
+
+    #[test]
+    fn test_syntax_rules_hygiene() {
+        let swap = "(define-syntax swap! (syntax-rules () ((_ a b) (let ((tmp a)) (set! a b) (set! b tmp)))))";
+        let input = [
+            // the `tmp` the template binds doesn't capture the user's, whether it's local or global
+            (format!("{} (let ((tmp 1) (other 2)) (swap! tmp other) (- tmp other))", swap), Int(1)),
+            (format!("{} (define tmp 1) (define other 2) (swap! tmp other) (- tmp other)", swap), Int(1)),
+            // ... and the `t` that `my-or` binds doesn't capture the user's `t`
+            (
+                String::from(
+                    "(define-syntax my-or (syntax-rules () ((_ a b) (let ((t a)) (if t t b)))))
+                     (let ((t 5)) (my-or (< 2 1) t))",
+                ),
+                Int(5),
+            ),
+            // free names in the template mean what they meant where the macro was defined
+            (
+                String::from(
+                    "(define (helper x) (* x 2))
+                     (define-syntax double (syntax-rules () ((_ e) (helper e))))
+                     (let ((helper (lambda (x) 0))) (double 21))",
+                ),
+                Int(42),
+            ),
+            // unquoted parts of a quasiquoted template are code, so they're renamed too
+            (
+                String::from(
+                    "(define-syntax m (syntax-rules () ((_ e) `(x ,(let ((tmp 1)) (+ tmp e))))))
+                     (let ((tmp 10)) (m tmp))",
+                ),
+                List(vec![Sym(Symbol::new("x")), Int(11)].into()),
+            ),
+            (
+                String::from(
+                    "(define-syntax m (syntax-rules () ((_ e) `(tmp ,@(let ((tmp 1)) (cons tmp (cons e '()))) `(,tmp)))))
+                     (let ((tmp 10)) (m tmp))",
+                ),
+                List(vec![
+                    Sym(Symbol::new("tmp")), Int(1), Int(10),
+                    List(vec![
+                        Sym(Symbol::new("quasiquote")),
+                        List(vec![List(vec![Sym(Symbol::new("unquote")), Sym(Symbol::new("tmp"))].into())].into()),
+                    ].into()),
+                ].into()),
+            ),
+            (
+                String::from(
+                    "(define count 0)
+                     (define-syntax bump! (syntax-rules () ((_) (set! count (+ count 1)))))
+                     (let ((count 10)) (bump!) (bump!)) count",
+                ),
+                Int(2),
+            ),
+        ];
+        for (src, expected) in input {
+            assert_eq!(eval(&src).unwrap(), expected, "`{}`", src);
+        }
+    }
+}